@@ -1,5 +1,6 @@
 use hdk::prelude::*;
 use holo_hash::error::HoloHashError;
+use perspective_diff_sync_integrity::schema::SchemaViolation;
 use std::convert::Infallible;
 
 #[derive(thiserror::Error, Debug)]
@@ -16,10 +17,191 @@ pub enum SocialContextError {
     HoloHashError(#[from] HoloHashError),
     #[error("Internal Error. Error: {0}")]
     InternalError(&'static str),
+    #[error("Entry {0} not found, it may not have been gossiped to us yet")]
+    EntryNotFound(AnyDhtHash),
+    #[error("Entry {0} does not contain app entry data")]
+    NoAppEntry(AnyDhtHash),
+    #[error("Snapshot {0} is linked but could not be fetched")]
+    SnapshotMissing(EntryHash),
+    #[error("Revision graph contains a cycle")]
+    DagCycle,
+    #[error("Revision graph references revisions that have not been gossiped to us yet")]
+    MissingRevisions,
+    #[error("No current revision yet, commit or pull first")]
+    NoCurrentRevision,
     #[error("No common ancestor found")]
     NoCommonAncestorFound,
     #[error("No did found")]
     NoDidFound,
+    #[error("Link by {author} has an invalid signature: {reason}")]
+    InvalidLinkSignature { author: String, reason: String },
+    #[error("Spam limit of {limit} commits within {window_s} seconds exceeded")]
+    SpamLimitExceeded { limit: usize, window_s: i64 },
+    #[error("The group key of this neighbourhood has not been shared with us yet")]
+    GroupKeyNotShared,
+    #[error("Diff could not be unsealed with the group key")]
+    UnsealFailed,
+    #[error("Only members with the writer or admin role can commit to this neighbourhood")]
+    NotAWriter,
+    #[error("{} links violate the link schema: {}", .violations.len(), describe_violations(.violations))]
+    SchemaViolation { violations: Vec<SchemaViolation> },
+    #[error("Signal not recognized")]
+    SignalNotRecognized,
+    #[error("Merge {0} can't be reverted, revert the commits it merged instead")]
+    CannotRevertMerge(ActionHash),
+    #[error("Pages need a limit of at least one link")]
+    InvalidPageLimit,
+}
+
+fn describe_violations(violations: &[SchemaViolation]) -> String {
+    violations
+        .iter()
+        .map(|violation| {
+            format!(
+                "({:?} {:?} {:?}) by {}: {}",
+                violation.link.data.source,
+                violation.link.data.predicate,
+                violation.link.data.target,
+                violation.link.author,
+                violation.reason
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
 }
 
 pub type SocialContextResult<T> = Result<T, SocialContextError>;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    Serialization,
+    Wasm,
+    Internal,
+    EntryNotFound,
+    NoAppEntry,
+    SnapshotMissing,
+    DagCycle,
+    MissingRevisions,
+    NoCurrentRevision,
+    NoCommonAncestor,
+    NoDid,
+    InvalidLinkSignature,
+    SpamLimitExceeded,
+    GroupKeyNotShared,
+    UnsealFailed,
+    NotAWriter,
+    SchemaViolation,
+    SignalNotRecognized,
+    CannotRevertMerge,
+    InvalidPageLimit,
+}
+
+/// The error externs return to clients, JSON encoded as the message of a guest WasmError.
+/// retryable is set for errors that can go away by themselves, like entries that were not
+/// gossiped to us yet, as opposed to invalid input or a corrupted revision graph.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ZomeError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub hash: Option<String>,
+    pub did: Option<String>,
+    pub retryable: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub violations: Vec<SchemaViolation>,
+}
+
+impl From<SocialContextError> for ZomeError {
+    fn from(error: SocialContextError) -> Self {
+        let message = error.to_string();
+        let (code, retryable) = match &error {
+            SocialContextError::Serialization(_) | SocialContextError::EntryError(_) => {
+                (ErrorCode::Serialization, false)
+            }
+            SocialContextError::Wasm(_) => (ErrorCode::Wasm, false),
+            SocialContextError::Infallible(_)
+            | SocialContextError::HoloHashError(_)
+            | SocialContextError::InternalError(_) => (ErrorCode::Internal, false),
+            SocialContextError::EntryNotFound(_) => (ErrorCode::EntryNotFound, true),
+            SocialContextError::NoAppEntry(_) => (ErrorCode::NoAppEntry, false),
+            SocialContextError::SnapshotMissing(_) => (ErrorCode::SnapshotMissing, true),
+            SocialContextError::DagCycle => (ErrorCode::DagCycle, false),
+            SocialContextError::MissingRevisions => (ErrorCode::MissingRevisions, true),
+            //Goes away once a pull found a revision of the other agents
+            SocialContextError::NoCurrentRevision => (ErrorCode::NoCurrentRevision, true),
+            //Usually the history of the other side has not reached us yet
+            SocialContextError::NoCommonAncestorFound => (ErrorCode::NoCommonAncestor, true),
+            SocialContextError::NoDidFound => (ErrorCode::NoDid, false),
+            SocialContextError::InvalidLinkSignature { .. } => {
+                (ErrorCode::InvalidLinkSignature, false)
+            }
+            SocialContextError::SpamLimitExceeded { .. } => (ErrorCode::SpamLimitExceeded, true),
+            SocialContextError::GroupKeyNotShared => (ErrorCode::GroupKeyNotShared, true),
+            SocialContextError::UnsealFailed => (ErrorCode::UnsealFailed, false),
+            SocialContextError::NotAWriter => (ErrorCode::NotAWriter, false),
+            SocialContextError::SchemaViolation { .. } => (ErrorCode::SchemaViolation, false),
+            SocialContextError::SignalNotRecognized => (ErrorCode::SignalNotRecognized, false),
+            SocialContextError::CannotRevertMerge(_) => (ErrorCode::CannotRevertMerge, false),
+            SocialContextError::InvalidPageLimit => (ErrorCode::InvalidPageLimit, false),
+        };
+        let mut zome_error = ZomeError {
+            code,
+            message,
+            hash: None,
+            did: None,
+            retryable,
+            violations: vec![],
+        };
+        match error {
+            SocialContextError::EntryNotFound(hash) => zome_error.hash = Some(hash.to_string()),
+            SocialContextError::NoAppEntry(hash) => zome_error.hash = Some(hash.to_string()),
+            SocialContextError::SnapshotMissing(hash) => zome_error.hash = Some(hash.to_string()),
+            SocialContextError::CannotRevertMerge(hash) => zome_error.hash = Some(hash.to_string()),
+            SocialContextError::InvalidLinkSignature { author, .. } => {
+                zome_error.did = Some(author)
+            }
+            SocialContextError::SchemaViolation { violations } => {
+                zome_error.violations = violations
+            }
+            _ => {}
+        };
+        zome_error
+    }
+}
+
+impl From<SocialContextError> for WasmError {
+    fn from(error: SocialContextError) -> Self {
+        let error = ZomeError::from(error);
+        match serde_json::to_string(&error) {
+            Ok(json) => wasm_error!(WasmErrorInner::Guest(json)),
+            Err(_) => wasm_error!(WasmErrorInner::Guest(error.message)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hdk::prelude::*;
+
+    use super::{ErrorCode, SocialContextError, ZomeError};
+
+    #[test]
+    fn converts_to_structured_errors() {
+        let hash = ActionHash::from_raw_36(vec![0xdb; 36]);
+        let error = ZomeError::from(SocialContextError::EntryNotFound(hash.clone().into()));
+        assert_eq!(error.code, ErrorCode::EntryNotFound);
+        assert_eq!(error.hash, Some(hash.to_string()));
+        assert!(error.retryable);
+
+        let error = ZomeError::from(SocialContextError::InvalidLinkSignature {
+            author: String::from("did:test:mallory"),
+            reason: String::from("forged"),
+        });
+        assert_eq!(error.did, Some(String::from("did:test:mallory")));
+        assert!(!error.retryable);
+
+        let json = serde_json::to_value(ZomeError::from(SocialContextError::DagCycle)).unwrap();
+        assert_eq!(json["code"], "dag_cycle");
+        assert!(json.get("violations").is_none());
+    }
+}
@@ -1,5 +1,8 @@
+use chrono::{DateTime, Utc};
 use hdk::prelude::*;
-use perspective_diff_sync_integrity::PerspectiveExpression;
+use perspective_diff_sync_integrity::{
+    CommitMetadata, LinkExpression, PerspectiveDiff, PerspectiveExpression,
+};
 
 use crate::Hash;
 
@@ -22,8 +25,46 @@ pub struct SignalData {
     pub payload: PerspectiveExpression,
 }
 
+#[derive(Serialize, Deserialize, Clone, SerializedBytes, Debug)]
+pub struct RevisionHistoryArguments {
+    /// Revision to start from, the current revision if None
+    pub from: Option<Hash>,
+    pub limit: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, SerializedBytes, Debug)]
+pub struct DiffBetweenArguments {
+    pub from: Hash,
+    pub to: Hash,
+}
+
+#[derive(Serialize, Deserialize, Clone, SerializedBytes, Debug)]
+pub struct CommitWithMetadataArguments {
+    pub diff: PerspectiveDiff,
+    pub metadata: CommitMetadata,
+}
+
+/// Pattern for query_links, every field that is set has to match
+#[derive(Serialize, Deserialize, Clone, SerializedBytes, Debug, Default)]
+#[serde(default)]
+pub struct LinkQuery {
+    pub source: Option<String>,
+    pub predicate: Option<String>,
+    pub target: Option<String>,
+    pub author: Option<String>,
+    pub from_date: Option<DateTime<Utc>>,
+    pub until_date: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, SerializedBytes, Debug)]
+pub struct RenderPageArguments {
+    /// Last link of the previous page, None for the first page
+    pub cursor: Option<LinkExpression>,
+    pub limit: usize,
+}
+
 #[derive(Serialize, Deserialize, Clone, SerializedBytes, Debug)]
 pub struct PullArguments {
     pub hash: Hash,
-    pub is_scribe: bool,
 }
@@ -2,17 +2,25 @@
 extern crate lazy_static;
 
 use hdk::prelude::*;
-use inputs::PullArguments;
-use lazy_static::lazy_static;
+use inputs::{
+    CommitWithMetadataArguments, DiffBetweenArguments, LinkQuery, PullArguments,
+    RenderPageArguments, RevisionHistoryArguments,
+};
 
+use perspective_diff_sync_integrity::membership::{
+    Invitation, Membership, ReceivedInvitation, Role,
+};
+use perspective_diff_sync_integrity::moderation::BanList;
+use perspective_diff_sync_integrity::properties::DnaProperties;
 use perspective_diff_sync_integrity::{
-    HashBroadcast, OnlineAgent, OnlineAgentAndAction, Perspective, PerspectiveDiff,
-    PerspectiveExpression, PullResult,
+    HashBroadcast, LinkExpression, OnlineAgent, OnlineAgentAndAction, Perspective, PerspectiveDiff,
+    PerspectiveExpression, PullResult, RenderPage, RevisionHistoryEntry, SnapshotStatus,
 };
 
 mod errors;
 mod inputs;
 mod link_adapter;
+mod membership;
 mod retriever;
 mod telepresence;
 mod utils;
@@ -38,7 +46,7 @@ fn init(_: ()) -> ExternResult<InitCallbackResult> {
         functions,
     })?;
     link_adapter::commit::add_active_agent_link::<retriever::HolochainRetreiver>()
-        .map_err(|error| utils::err(&format!("{}", error)))?;
+        .map_err(WasmError::from)?;
     Ok(InitCallbackResult::Pass)
 }
 
@@ -46,33 +54,189 @@ fn init(_: ()) -> ExternResult<InitCallbackResult> {
 
 #[hdk_extern]
 pub fn commit(diff: PerspectiveDiff) -> ExternResult<Hash> {
-    link_adapter::commit::commit::<retriever::HolochainRetreiver>(diff)
-        .map_err(|error| utils::err(&format!("{}", error)))
+    let properties = DnaProperties::load()?;
+    ensure_writer(&properties)?;
+    link_adapter::commit::commit::<retriever::HolochainRetreiver>(diff, &properties)
+        .map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn commit_with_metadata(args: CommitWithMetadataArguments) -> ExternResult<Hash> {
+    let properties = DnaProperties::load()?;
+    ensure_writer(&properties)?;
+    //The author is always the DID of this agent, clients can't attribute commits to others
+    let mut metadata = args.metadata;
+    metadata.author = telepresence::status::get_my_did().map_err(WasmError::from)?;
+    link_adapter::commit::commit_with_metadata::<retriever::HolochainRetreiver>(
+        args.diff,
+        Some(metadata),
+        &properties,
+    )
+    .map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn revert(hash: Hash) -> ExternResult<Hash> {
+    let properties = DnaProperties::load()?;
+    ensure_writer(&properties)?;
+    link_adapter::revert::revert::<retriever::HolochainRetreiver>(hash, &properties)
+        .map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn checkout(hash: Hash) -> ExternResult<Hash> {
+    let properties = DnaProperties::load()?;
+    ensure_writer(&properties)?;
+    link_adapter::revert::checkout::<retriever::HolochainRetreiver>(hash, &properties)
+        .map_err(WasmError::from)
+}
+
+/// Fails early for agents validation would reject the commit of anyway
+fn ensure_writer(properties: &DnaProperties) -> ExternResult<()> {
+    let can_write = membership::can_write(properties).map_err(WasmError::from)?;
+    if !can_write {
+        return Err(errors::SocialContextError::NotAWriter.into());
+    }
+    Ok(())
 }
 
 #[hdk_extern]
 pub fn current_revision(_: ()) -> ExternResult<Option<Hash>> {
     link_adapter::revisions::current_revision::<retriever::HolochainRetreiver>()
-        .map_err(|error| utils::err(&format!("{}", error)))
+        .map_err(WasmError::from)
         .map(|val| val.map(|val| val.hash))
 }
 
 #[hdk_extern]
 pub fn sync(_: ()) -> ExternResult<Option<Hash>> {
-    link_adapter::commit::broadcast_current::<retriever::HolochainRetreiver>()
-        .map_err(|error| utils::err(&format!("{}", error)))
+    let properties = DnaProperties::load()?;
+    //Snapshots scheduled by commit get created here, outside of the commit call. Like sharing
+    //the group key this is housekeeping, which must not keep us from broadcasting our revision.
+    let snapshots = link_adapter::snapshots::create_pending_snapshots::<
+        retriever::HolochainRetreiver,
+    >(&properties);
+    if let Err(error) = snapshots {
+        debug!(
+            "===PerspectiveDiffSync.sync(): Could not create pending snapshots: {}",
+            error
+        );
+    }
+    if properties.encrypted {
+        if let Err(error) = retriever::encryption::share_group_key(&properties) {
+            debug!(
+                "===PerspectiveDiffSync.sync(): Could not share the group key: {}",
+                error
+            );
+        }
+    }
+    //Read only members don't announce themselves, so they never get elected as scribe
+    if !membership::can_write(&properties).map_err(WasmError::from)? {
+        return link_adapter::revisions::current_revision::<retriever::HolochainRetreiver>()
+            .map_err(WasmError::from)
+            .map(|val| val.map(|val| val.hash));
+    }
+    link_adapter::commit::broadcast_current::<retriever::HolochainRetreiver>(&properties)
+        .map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn share_group_key(_: ()) -> ExternResult<Vec<AgentPubKey>> {
+    let properties = DnaProperties::load()?;
+    retriever::encryption::share_group_key(&properties).map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn create_pending_snapshots(_: ()) -> ExternResult<Vec<Hash>> {
+    let properties = DnaProperties::load()?;
+    link_adapter::snapshots::create_pending_snapshots::<retriever::HolochainRetreiver>(&properties)
+        .map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn snapshot_status(hash: Hash) -> ExternResult<SnapshotStatus> {
+    link_adapter::snapshots::snapshot_status::<retriever::HolochainRetreiver>(hash)
+        .map_err(WasmError::from)
 }
 
 #[hdk_extern]
 pub fn pull(args: PullArguments) -> ExternResult<PullResult> {
-    link_adapter::pull::pull::<retriever::HolochainRetreiver>(true, args.hash, args.is_scribe)
-        .map_err(|error| utils::err(&format!("{}", error)))
+    let properties = DnaProperties::load()?;
+    let is_scribe = is_scribe(&properties).map_err(WasmError::from)?;
+    let can_write = membership::can_write(&properties).map_err(WasmError::from)?;
+    link_adapter::pull::pull::<retriever::HolochainRetreiver>(
+        true,
+        args.hash,
+        is_scribe,
+        can_write,
+        &properties,
+    )
+    .map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn am_i_scribe(_: ()) -> ExternResult<bool> {
+    let properties = DnaProperties::load()?;
+    is_scribe(&properties).map_err(WasmError::from)
+}
+
+fn is_scribe(properties: &DnaProperties) -> errors::SocialContextResult<bool> {
+    //Merging means committing, which read only members can't do
+    if !membership::can_write(properties)? {
+        return Ok(false);
+    }
+    //Without a DID we can't take part in the election, so we leave merging to the others
+    let me = match telepresence::status::get_my_did()? {
+        Some(me) => me,
+        None => return Ok(false),
+    };
+    link_adapter::scribe::am_i_scribe::<retriever::HolochainRetreiver>(
+        &me,
+        utils::get_now()?,
+        properties,
+    )
 }
 
 #[hdk_extern]
 pub fn render(_: ()) -> ExternResult<Perspective> {
-    link_adapter::render::render::<retriever::HolochainRetreiver>()
-        .map_err(|error| utils::err(&format!("{}", error)))
+    link_adapter::render::render::<retriever::HolochainRetreiver>().map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn render_page(args: RenderPageArguments) -> ExternResult<RenderPage> {
+    let properties = DnaProperties::load()?;
+    link_adapter::render::render_page::<retriever::HolochainRetreiver>(
+        args.cursor,
+        args.limit,
+        &properties,
+    )
+    .map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn query_links(query: LinkQuery) -> ExternResult<Vec<LinkExpression>> {
+    link_adapter::query::query_links::<retriever::HolochainRetreiver>(query)
+        .map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn render_at(hash: Hash) -> ExternResult<Perspective> {
+    link_adapter::render::render_at::<retriever::HolochainRetreiver>(hash).map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn get_revision_history(
+    args: RevisionHistoryArguments,
+) -> ExternResult<Vec<RevisionHistoryEntry>> {
+    link_adapter::history::get_revision_history::<retriever::HolochainRetreiver>(
+        args.from, args.limit,
+    )
+    .map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn diff_between(args: DiffBetweenArguments) -> ExternResult<PerspectiveDiff> {
+    link_adapter::history::diff_between::<retriever::HolochainRetreiver>(args.from, args.to)
+        .map_err(WasmError::from)
 }
 
 #[hdk_extern]
@@ -83,7 +247,7 @@ pub fn update_current_revision(_hash: Hash) -> ExternResult<()> {
             _hash,
             utils::get_now().unwrap(),
         )
-        .map_err(|err| utils::err(&format!("{}", err)))?;
+        .map_err(WasmError::from)?;
     }
     Ok(())
 }
@@ -95,46 +259,96 @@ fn recv_remote_signal(signal: SerializedBytes) -> ExternResult<()> {
     //Check if its a normal diff expression signal
     match HashBroadcast::try_from(signal.clone()) {
         Ok(broadcast) => {
-            link_adapter::pull::handle_broadcast::<retriever::HolochainRetreiver>(broadcast)
-                .map_err(|err| utils::err(&format!("{}", err)))?;
+            //The claimed author is only trusted if the sending agent key is linked to it
+            let sender_did = telepresence::status::get_agents_did_key(call_info()?.provenance)
+                .map_err(WasmError::from)?;
+            link_adapter::pull::handle_broadcast::<retriever::HolochainRetreiver>(
+                broadcast,
+                sender_did,
+                &DnaProperties::load()?,
+            )
+            .map_err(WasmError::from)?;
         }
         //Check if its a broadcast message
         Err(_) => match PerspectiveExpression::try_from(signal.clone()) {
             Ok(sig) => emit_signal(sig)?,
             //Check if its an online ping
-            Err(_) => return Err(utils::err(&format!("Signal not recognized: {:?}", signal))),
+            Err(_) => return Err(errors::SocialContextError::SignalNotRecognized.into()),
         },
     };
     Ok(())
 }
 
+// Membership
+
+#[hdk_extern]
+pub fn create_invitation(invitation: Invitation) -> ExternResult<Hash> {
+    membership::create_invitation(invitation).map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn get_invitations(did: String) -> ExternResult<Vec<ReceivedInvitation>> {
+    membership::get_invitations(did).map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn join(membership: Membership) -> ExternResult<Hash> {
+    membership::join(membership).map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn get_members(_: ()) -> ExternResult<Vec<Membership>> {
+    membership::get_members().map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn my_role(_: ()) -> ExternResult<Option<Role>> {
+    membership::my_role().map_err(WasmError::from)
+}
+
+// Moderation
+
+/// Replaces the ban list, so bans are issued and lifted by issuing a list with or without the DID
+#[hdk_extern]
+pub fn issue_ban_list(ban_list: BanList) -> ExternResult<Hash> {
+    link_adapter::moderation::issue_ban_list::<retriever::HolochainRetreiver>(ban_list)
+        .map_err(WasmError::from)
+}
+
+#[hdk_extern]
+pub fn get_ban_list(_: ()) -> ExternResult<Option<BanList>> {
+    let now = utils::get_now().map_err(WasmError::from)?;
+    link_adapter::moderation::ban_list_at::<retriever::HolochainRetreiver>(now)
+        .map_err(WasmError::from)
+}
+
 // Telepresence implementation
 
 #[hdk_extern]
 pub fn set_online_status(status: PerspectiveExpression) -> ExternResult<()> {
-    telepresence::status::set_online_status(status)
-        .map_err(|error| utils::err(&format!("{}", error)))?;
+    telepresence::status::set_online_status(status).map_err(WasmError::from)?;
     Ok(())
 }
 
 #[hdk_extern]
 pub fn create_did_pub_key_link(did: String) -> ExternResult<()> {
-    telepresence::status::create_did_pub_key_link(did)
-        .map_err(|error| utils::err(&format!("{}", error)))?;
+    telepresence::status::create_did_pub_key_link(did).map_err(WasmError::from)?;
+    //Publishing our encryption key lets members share the group key with us
+    if DnaProperties::load()?.encrypted {
+        retriever::encryption::my_encryption_key().map_err(WasmError::from)?;
+    }
     Ok(())
 }
 
 #[hdk_extern]
 pub fn get_online_agents(_: ()) -> ExternResult<Vec<OnlineAgent>> {
-    let res = telepresence::status::get_online_agents()
-        .map_err(|error| utils::err(&format!("{}", error)))?;
+    let res = telepresence::status::get_online_agents().map_err(WasmError::from)?;
     Ok(res)
 }
 
 #[hdk_extern]
 pub fn get_online_status(_: ()) -> ExternResult<OnlineAgentAndAction> {
-    let res = telepresence::status::get_online_status()
-        .map_err(|error| utils::err(&format!("{}", error)))?;
+    let res = telepresence::status::get_online_status().map_err(WasmError::from)?;
     Ok(res)
 }
 
@@ -146,36 +360,24 @@ pub fn get_agents_status(agent: AgentPubKey) -> ExternResult<Option<OnlineAgent>
 
 #[hdk_extern]
 pub fn send_signal(signal_data: inputs::SignalData) -> ExternResult<PerspectiveExpression> {
-    let res = telepresence::signal::send_signal(signal_data)
-        .map_err(|error| utils::err(&format!("{}", error)))?;
+    let res = telepresence::signal::send_signal(signal_data).map_err(WasmError::from)?;
     Ok(res)
 }
 
 #[hdk_extern]
 pub fn send_broadcast(data: PerspectiveExpression) -> ExternResult<PerspectiveExpression> {
-    let res = telepresence::signal::send_broadcast(data)
-        .map_err(|error| utils::err(&format!("{}", error)))?;
+    let res = telepresence::signal::send_broadcast(data).map_err(WasmError::from)?;
     Ok(res)
 }
 
 #[hdk_extern]
 pub fn get_active_agents(_: ()) -> ExternResult<Vec<AgentPubKey>> {
-    let res = retriever::holochain::get_active_agents()
-        .map_err(|error| utils::err(&format!("{}", error)))?;
+    let res = retriever::holochain::get_active_agents().map_err(WasmError::from)?;
     Ok(res)
 }
 
 #[hdk_extern]
 pub fn get_others(_: ()) -> ExternResult<Vec<String>> {
-    let res =
-        telepresence::status::get_others().map_err(|error| utils::err(&format!("{}", error)))?;
+    let res = telepresence::status::get_others().map_err(WasmError::from)?;
     Ok(res)
 }
-
-//not loading from DNA properies since dna zome properties is always null for some reason
-lazy_static! {
-    pub static ref ACTIVE_AGENT_DURATION: chrono::Duration = chrono::Duration::seconds(3600);
-    pub static ref ENABLE_SIGNALS: bool = true;
-    pub static ref SNAPSHOT_INTERVAL: usize = 100;
-    pub static ref CHUNK_SIZE: u16 = 10000;
-}
@@ -1,9 +1,11 @@
 use hdk::prelude::*;
 use perspective_diff_sync_integrity::{
-    EntryTypes, LinkExpression, PerspectiveDiff,
+    EntryTypes, LinkExpression, PerspectiveDiff, PerspectiveDiffEntryReference,
 };
+use perspective_diff_sync_integrity::compact::{decode_diff, CompactPerspectiveDiff};
+use perspective_diff_sync_integrity::properties::{DiffEncoding, DnaProperties};
 
-use crate::{Hash, CHUNK_SIZE};
+use crate::Hash;
 use crate::errors::{SocialContextResult};
 use crate::retriever::{PerspectiveDiffRetreiver};
 
@@ -53,25 +55,25 @@ impl ChunkedDiffs {
         }
     }
 
-    pub fn into_entries<Retreiver: PerspectiveDiffRetreiver>(self) -> SocialContextResult<Vec<Hash>> {
+    pub fn into_entries<Retreiver: PerspectiveDiffRetreiver>(self, properties: &DnaProperties) -> SocialContextResult<Vec<Hash>> {
         debug!("ChunkedDiffs.into_entries()");
         self.chunks
             .into_iter()
             .map(|chunk_diff| {
                 debug!("ChunkedDiffs writing chunk of size: {}", chunk_diff.total_diff_number());
-                Retreiver::create_entry(EntryTypes::PerspectiveDiff(chunk_diff))
+                create_diff_entry::<Retreiver>(chunk_diff, properties)
             })
             .collect() 
     }
 
-    pub fn from_entries<Retreiver: PerspectiveDiffRetreiver>(hashes: Vec<Hash>) -> SocialContextResult<Self> {
+    pub fn from_entries<Retreiver: PerspectiveDiffRetreiver>(hashes: Vec<Hash>, max: u16) -> SocialContextResult<Self> {
         let mut diffs = Vec::new();
         for hash in hashes.into_iter() {
-            diffs.push(Retreiver::get::<PerspectiveDiff>(hash)?);
+            diffs.push(get_diff::<Retreiver>(hash)?);
         }
 
         Ok(ChunkedDiffs {
-            max_changes_per_chunk: *CHUNK_SIZE,
+            max_changes_per_chunk: max,
             chunks: diffs,
         })
     }
@@ -87,10 +89,96 @@ impl ChunkedDiffs {
     }
 }
 
+/// A diff entry read in whichever encoding it was written, so old and new entries can be mixed
+/// freely. Validation already held it to the chunk size of the DNA, so reading only bounds it by
+/// the largest chunk size there is.
+struct StoredDiff(PerspectiveDiff);
+
+impl TryFrom<SerializedBytes> for StoredDiff {
+    type Error = SerializedBytesError;
+
+    fn try_from(bytes: SerializedBytes) -> Result<Self, Self::Error> {
+        Ok(StoredDiff(decode_diff(bytes, u16::MAX)?))
+    }
+}
+
+/// Writes a diff entry using the encoding of the neighbourhood, sealed if it is encrypted
+pub fn create_diff_entry<Retreiver: PerspectiveDiffRetreiver>(
+    diff: PerspectiveDiff,
+    properties: &DnaProperties,
+) -> SocialContextResult<Hash> {
+    let compact = match properties.diff_encoding {
+        DiffEncoding::Plain => None,
+        DiffEncoding::Compact => Some(CompactPerspectiveDiff::encode(
+            &diff,
+            false,
+            properties.chunk_size,
+        )?),
+        DiffEncoding::CompactCompressed => Some(CompactPerspectiveDiff::encode(
+            &diff,
+            true,
+            properties.chunk_size,
+        )?),
+    };
+    if properties.encrypted {
+        let data = match compact {
+            Some(compact) => SerializedBytes::try_from(compact)?,
+            None => SerializedBytes::try_from(diff)?,
+        };
+        return Retreiver::create_entry(EntryTypes::SealedPerspectiveDiff(Retreiver::seal(data)?));
+    }
+    match compact {
+        Some(compact) => Retreiver::create_entry(EntryTypes::CompactPerspectiveDiff(compact)),
+        None => Retreiver::create_entry(EntryTypes::PerspectiveDiff(diff)),
+    }
+}
+
+/// Writes the diff of a commit or merge. Large diffs are split into chunks, so no single entry
+/// gets too big to publish, gossip or validate. Returns the first entry and, if split, all chunks.
+pub fn create_reference_diff<Retreiver: PerspectiveDiffRetreiver>(
+    diff: PerspectiveDiff,
+    properties: &DnaProperties,
+) -> SocialContextResult<(Hash, Option<Vec<Hash>>)> {
+    if diff.total_diff_number() > properties.chunk_size as usize {
+        let mut chunked_diffs = ChunkedDiffs::new(properties.chunk_size);
+        chunked_diffs.add_additions(diff.additions);
+        chunked_diffs.add_removals(diff.removals);
+        let chunks = chunked_diffs.into_entries::<Retreiver>(properties)?;
+        Ok((chunks[0].clone(), Some(chunks)))
+    } else {
+        Ok((create_diff_entry::<Retreiver>(diff, properties)?, None))
+    }
+}
+
+/// Reads a diff entry written by create_diff_entry, whatever its encoding
+pub fn get_diff<Retreiver: PerspectiveDiffRetreiver>(
+    hash: Hash,
+) -> SocialContextResult<PerspectiveDiff> {
+    Ok(Retreiver::get::<StoredDiff>(hash)?.0)
+}
+
+/// Loads the diff of a commit, reassembling it if commit had to split it into chunks
+pub fn get_reference_diff<Retreiver: PerspectiveDiffRetreiver>(
+    reference: &PerspectiveDiffEntryReference,
+) -> SocialContextResult<PerspectiveDiff> {
+    match &reference.diff_chunks {
+        Some(chunks) => Ok(
+            ChunkedDiffs::from_entries::<Retreiver>(chunks.clone(), u16::MAX)?
+                .into_aggregated_diff(),
+        ),
+        None => get_diff::<Retreiver>(reference.diff.clone()),
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
+    use perspective_diff_sync_integrity::properties::{DiffEncoding, DnaProperties};
+    use perspective_diff_sync_integrity::encryption::SealedPerspectiveDiff;
+    use std::convert::TryFrom;
+
     use super::ChunkedDiffs;
+    use crate::link_adapter::tests::{reset_mocked_graph, test_properties};
     use crate::utils::create_link_expression;
     use crate::retriever::{GLOBAL_MOCKED_GRAPH, MockPerspectiveGraph};
 
@@ -198,10 +286,55 @@ mod tests {
         assert_eq!(chunks.chunks.len(), 10);
 
         let chunks_clone = chunks.clone();
-        let hashes = chunks.into_entries::<MockPerspectiveGraph>().expect("into_entries does not error");
-        let read_chunks = ChunkedDiffs::from_entries::<MockPerspectiveGraph>(hashes).expect("from_entries does not error");
+        let hashes = chunks.into_entries::<MockPerspectiveGraph>(&DnaProperties::default()).expect("into_entries does not error");
+        let read_chunks = ChunkedDiffs::from_entries::<MockPerspectiveGraph>(hashes, 500).expect("from_entries does not error");
 
         assert_eq!(read_chunks.chunks.len(), 10);
         assert_eq!(format!("{:?}", read_chunks.chunks), format!("{:?}", chunks_clone.chunks));
     }
+
+    #[test]
+    fn can_read_mixed_encodings() {
+        reset_mocked_graph();
+
+        let mut chunks = ChunkedDiffs::new(100);
+        let mut big_diff_add = Vec::new();
+        for i in 0..400 {
+            big_diff_add.push(create_link_expression("a", &format!("{}", i)));
+        }
+        chunks.add_additions(big_diff_add);
+        let chunks_clone = chunks.clone();
+
+        let settings = [
+            (DiffEncoding::Plain, false),
+            (DiffEncoding::Compact, false),
+            (DiffEncoding::CompactCompressed, false),
+            (DiffEncoding::CompactCompressed, true),
+        ];
+        let mut hashes = Vec::new();
+        for (chunk, (encoding, encrypted)) in chunks.chunks.into_iter().zip(settings.iter()) {
+            let properties = DnaProperties {
+                diff_encoding: encoding.clone(),
+                encrypted: *encrypted,
+                ..test_properties()
+            };
+            hashes.push(
+                super::create_diff_entry::<MockPerspectiveGraph>(chunk, &properties)
+                    .expect("create_diff_entry does not error"),
+            );
+        }
+        let stored = GLOBAL_MOCKED_GRAPH
+            .lock()
+            .unwrap()
+            .graph_map
+            .get(&hashes[3])
+            .unwrap()
+            .clone();
+        assert!(SealedPerspectiveDiff::try_from(stored).is_ok());
+
+        let read_chunks = ChunkedDiffs::from_entries::<MockPerspectiveGraph>(hashes, 100)
+            .expect("from_entries does not error");
+
+        assert_eq!(format!("{:?}", read_chunks.chunks), format!("{:?}", chunks_clone.chunks));
+    }
 }
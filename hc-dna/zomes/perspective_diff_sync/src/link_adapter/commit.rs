@@ -1,24 +1,69 @@
 use chrono::Timelike;
 use hdk::prelude::*;
+use perspective_diff_sync_integrity::properties::DnaProperties;
+use perspective_diff_sync_integrity::signatures::verify_perspective_diff;
 use perspective_diff_sync_integrity::{
-    EntryTypes, HashBroadcast, LinkTypes, PerspectiveDiff, PerspectiveDiffEntryReference,
+    CommitMetadata, EntryTypes, HashBroadcast, LinkTypes, LocalPendingSnapshot, PerspectiveDiff,
+    PerspectiveDiffEntryReference,
 };
 
-use crate::errors::SocialContextResult;
+use crate::errors::{SocialContextError, SocialContextResult};
+use crate::link_adapter::chunked_diffs::{create_reference_diff, get_reference_diff};
+use crate::link_adapter::moderation::{ban_list_changes, banned_at};
 use crate::link_adapter::revisions::{current_revision, update_current_revision};
-use crate::link_adapter::snapshots::generate_snapshot;
+use crate::retriever::encryption;
 use crate::retriever::holochain::{get_active_agent_anchor, get_active_agents};
 use crate::retriever::PerspectiveDiffRetreiver;
 use crate::telepresence::status::get_my_did;
 use crate::utils::get_now;
-use crate::{Hash, ENABLE_SIGNALS, SNAPSHOT_INTERVAL};
+use crate::Hash;
 
 pub fn commit<Retriever: PerspectiveDiffRetreiver>(
     diff: PerspectiveDiff,
+    properties: &DnaProperties,
+) -> SocialContextResult<HoloHash<holo_hash::hash_type::Action>> {
+    commit_with_metadata::<Retriever>(diff, None, properties)
+}
+
+/// Whether one more commit stays within `enforce_spam_limit`. Validation counts merges like
+/// any other commit, so pull checks this before merging as well.
+pub fn within_commit_rate<Retriever: PerspectiveDiffRetreiver>(
+    properties: &DnaProperties,
+) -> SocialContextResult<bool> {
+    if properties.enforce_spam_limit == 0 {
+        return Ok(true);
+    }
+    let window_start = get_now()? - chrono::Duration::seconds(properties.spam_limit_window_s);
+    Ok(Retriever::commits_since(window_start)? < properties.enforce_spam_limit)
+}
+
+pub fn commit_with_metadata<Retriever: PerspectiveDiffRetreiver>(
+    diff: PerspectiveDiff,
+    metadata: Option<CommitMetadata>,
+    properties: &DnaProperties,
 ) -> SocialContextResult<HoloHash<holo_hash::hash_type::Action>> {
     debug!("===PerspectiveDiffSync.commit(): Function start");
     let now_fn_start = get_now()?.time();
+    //Reject unsigned or forged links here, before they get rejected by validation on the DHT
+    verify_perspective_diff(&diff, properties.accept_unresolvable_dids).map_err(
+        |(link, error)| SocialContextError::InvalidLinkSignature {
+            author: link.author.clone(),
+            reason: error.to_string(),
+        },
+    )?;
+    if let Some(schema) = &properties.link_schema {
+        schema
+            .check_diff(&diff)
+            .map_err(|violations| SocialContextError::SchemaViolation { violations })?;
+    }
+    if !within_commit_rate::<Retriever>(properties)? {
+        return Err(SocialContextError::SpamLimitExceeded {
+            limit: properties.enforce_spam_limit,
+            window_s: properties.spam_limit_window_s,
+        });
+    }
     let current_revision = current_revision::<Retriever>()?;
+    let previous = current_revision.clone().map(|val| val.hash);
 
     let mut entries_since_snapshot = 0;
     if current_revision.is_some() {
@@ -34,7 +79,7 @@ pub fn commit<Retriever: PerspectiveDiffRetreiver>(
     //Add one since we are comitting an entry here
     entries_since_snapshot += 1;
 
-    let create_snapshot_here = if entries_since_snapshot >= *SNAPSHOT_INTERVAL {
+    let create_snapshot_here = if entries_since_snapshot >= properties.snapshot_interval {
         entries_since_snapshot = 0;
         true
     } else {
@@ -42,11 +87,14 @@ pub fn commit<Retriever: PerspectiveDiffRetreiver>(
     };
 
     let now = get_now()?.time();
-    let diff_entry_create = Retriever::create_entry(EntryTypes::PerspectiveDiff(diff.clone()))?;
+    let (diff_entry_create, diff_chunks) =
+        create_reference_diff::<Retriever>(diff.clone(), properties)?;
     let diff_entry_ref_entry = PerspectiveDiffEntryReference {
         diff: diff_entry_create.clone(),
         parents: current_revision.map(|val| vec![val.hash]),
         diffs_since_snapshot: entries_since_snapshot,
+        metadata,
+        diff_chunks,
     };
     let diff_entry_reference = Retriever::create_entry(EntryTypes::PerspectiveDiffEntryReference(
         diff_entry_ref_entry.clone(),
@@ -62,20 +110,12 @@ pub fn commit<Retriever: PerspectiveDiffRetreiver>(
     );
 
     if create_snapshot_here {
-        //fetch all the diff's, we need a new function which will traverse graph and then return + diffs + next found snapshot
-        //create new snapshot linked from above diff_entry_reference
-        let snapshot = generate_snapshot(diff_entry_reference.clone())?;
-
-        let now = get_now()?.time();
-        Retriever::create_entry(EntryTypes::Snapshot(snapshot.clone()))?;
-        create_link(
-            hash_entry(diff_entry_ref_entry.clone())?,
-            hash_entry(snapshot)?,
-            LinkTypes::Snapshot,
-            LinkTag::new("snapshot"),
-        )?;
-        let after = get_now()?.time();
-        debug!("===PerspectiveDiffSync.commit() - Profiling: Took {} to create snapshot entry and link", (after - now).num_milliseconds());
+        //Creating the snapshot walks the whole history since the last one, so it is left to
+        //create_pending_snapshots instead of slowing down this commit
+        Retriever::update_pending_snapshot(LocalPendingSnapshot {
+            revision: diff_entry_reference.clone(),
+            pending: true,
+        })?;
     };
 
     let now = get_now()?;
@@ -88,14 +128,24 @@ pub fn commit<Retriever: PerspectiveDiffRetreiver>(
     );
     update_current_revision::<Retriever>(diff_entry_reference.clone(), now)?;
 
-    if *ENABLE_SIGNALS {
+    if properties.enable_signals {
         // let signal_data = PerspectiveDiffReference {
         //     diff,
         //     reference: diff_entry_ref_entry,
         //     reference_hash: diff_entry_reference.clone(),
         // };
         // send_revision_signal(signal_data)?;
-        broadcast_current::<Retriever>()?;
+        //Ban lists issued since our previous revision change the perspective the client shows
+        if let Some(previous) = previous {
+            let changes = ban_list_changes::<Retriever>(
+                previous,
+                &banned_at::<Retriever>(diff_entry_reference.clone())?,
+            )?;
+            if !changes.additions.is_empty() || !changes.removals.is_empty() {
+                emit_signal(changes)?;
+            }
+        }
+        broadcast_current::<Retriever>(properties)?;
     };
 
     let after_fn_end = get_now()?.time();
@@ -114,9 +164,9 @@ pub fn add_active_agent_link<Retriever: PerspectiveDiffRetreiver>() -> SocialCon
         Retriever::create_entry(EntryTypes::Anchor(agent_root_entry.clone()))?;
 
     let agent = agent_info()?.agent_initial_pubkey;
-    create_link(
-        hash_entry(agent_root_entry)?,
-        agent,
+    Retriever::create_link(
+        Retriever::hash_entry(agent_root_entry)?.into(),
+        agent.into(),
         LinkTypes::Index,
         LinkTag::new("active_agent"),
     )?;
@@ -125,15 +175,16 @@ pub fn add_active_agent_link<Retriever: PerspectiveDiffRetreiver>() -> SocialCon
     Ok(())
 }
 
-pub fn broadcast_current<Retriever: PerspectiveDiffRetreiver>() -> SocialContextResult<Option<Hash>>
-{
+pub fn broadcast_current<Retriever: PerspectiveDiffRetreiver>(
+    properties: &DnaProperties,
+) -> SocialContextResult<Option<Hash>> {
     let current = current_revision::<Retriever>()?;
 
     if current.is_some() {
         let current_revision = current.clone().unwrap();
         let entry_ref =
             Retriever::get::<PerspectiveDiffEntryReference>(current_revision.hash.clone())?;
-        let diff = Retriever::get::<PerspectiveDiff>(entry_ref.diff.clone())?;
+        let diff = broadcast_diff::<Retriever>(&entry_ref)?;
 
         let signal_data = HashBroadcast {
             reference: entry_ref,
@@ -142,7 +193,17 @@ pub fn broadcast_current<Retriever: PerspectiveDiffRetreiver>() -> SocialContext
             broadcast_author: get_my_did()?.unwrap(),
         };
 
-        let recent_agents = get_active_agents()?;
+        let mut recent_agents = get_active_agents()?;
+        //The signal carries the diff in the clear, so only agents holding the group key get it
+        if properties.encrypted {
+            let mut members = vec![];
+            for agent in recent_agents {
+                if encryption::is_member(agent.clone())? {
+                    members.push(agent);
+                }
+            }
+            recent_agents = members;
+        }
 
         let now = get_now()?.time();
         remote_signal(signal_data.get_sb()?, recent_agents.clone())?;
@@ -161,3 +222,346 @@ pub fn broadcast_current<Retriever: PerspectiveDiffRetreiver>() -> SocialContext
     };
     Ok(current.map(|rev| rev.hash))
 }
+
+/// The diff to send along with a broadcast of reference. Chunked diffs are too large for a
+/// single signal, receivers fetch their chunks from the hashes in the reference instead.
+pub fn broadcast_diff<Retriever: PerspectiveDiffRetreiver>(
+    reference: &PerspectiveDiffEntryReference,
+) -> SocialContextResult<PerspectiveDiff> {
+    match reference.diff_chunks {
+        Some(_) => Ok(PerspectiveDiff::new()),
+        None => get_reference_diff::<Retriever>(reference),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hdk::prelude::*;
+    use perspective_diff_sync_integrity::compact::CompactPerspectiveDiff;
+    use perspective_diff_sync_integrity::properties::{DiffEncoding, DnaProperties};
+    use perspective_diff_sync_integrity::schema::LinkSchema;
+    use perspective_diff_sync_integrity::{
+        CommitMetadata, LinkTypes, PerspectiveDiff, PerspectiveDiffEntryReference, Snapshot,
+        SnapshotStatus,
+    };
+
+    use super::{commit, commit_with_metadata};
+    use crate::errors::SocialContextError;
+    use crate::link_adapter::chunked_diffs::get_reference_diff;
+    use crate::link_adapter::render::render;
+    use crate::link_adapter::snapshots::{create_pending_snapshots, snapshot_status};
+    use crate::link_adapter::tests::{reset_mocked_graph, test_properties};
+    use crate::retriever::{
+        MockPerspectiveGraph, PerspectiveDiffRetreiver, CURRENT_REVISION, PERSPECTIVE_CACHE,
+    };
+    use crate::utils::create_link_expression;
+
+    #[test]
+    fn commit_schedules_snapshots_at_interval() {
+        reset_mocked_graph();
+
+        let properties = DnaProperties {
+            snapshot_interval: 2,
+            ..test_properties()
+        };
+
+        let mut links = vec![];
+        let mut references = vec![];
+        for i in 0..5 {
+            let link = create_link_expression("a", &format!("{}", i));
+            links.push(link.clone());
+            references.push(
+                commit::<MockPerspectiveGraph>(
+                    PerspectiveDiff {
+                        additions: vec![link],
+                        removals: vec![],
+                    },
+                    &properties,
+                )
+                .expect("commit does not error"),
+            );
+        }
+
+        let snapshot_of = |hash: &ActionHash| -> Option<Snapshot> {
+            let reference =
+                MockPerspectiveGraph::get::<PerspectiveDiffEntryReference>(hash.clone()).unwrap();
+            MockPerspectiveGraph::get_link_targets(
+                MockPerspectiveGraph::hash_entry(reference).unwrap().into(),
+                LinkTypes::Snapshot,
+                Some(LinkTag::new("snapshot")),
+            )
+            .unwrap()
+            .pop()
+            .map(|target| {
+                MockPerspectiveGraph::get_entry::<Snapshot>(target.into_entry_hash().unwrap())
+                    .unwrap()
+            })
+        };
+
+        //Commit only schedules the snapshots
+        assert!(snapshot_of(&references[1]).is_none());
+        assert_eq!(
+            snapshot_status::<MockPerspectiveGraph>(references[1].clone()).unwrap(),
+            SnapshotStatus::Pending
+        );
+        assert_eq!(
+            create_pending_snapshots::<MockPerspectiveGraph>(&properties).unwrap(),
+            vec![references[1].clone(), references[3].clone()]
+        );
+        assert!(
+            create_pending_snapshots::<MockPerspectiveGraph>(&properties)
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            snapshot_status::<MockPerspectiveGraph>(references[1].clone()).unwrap(),
+            SnapshotStatus::Created
+        );
+        assert_eq!(
+            snapshot_status::<MockPerspectiveGraph>(references[2].clone()).unwrap(),
+            SnapshotStatus::NotScheduled
+        );
+
+        assert!(snapshot_of(&references[0]).is_none());
+        assert!(snapshot_of(&references[2]).is_none());
+        assert!(snapshot_of(&references[4]).is_none());
+
+        //Snapshots only store the frontier of the history they include
+        let first_snapshot = snapshot_of(&references[1]).expect("snapshot after two commits");
+        assert_eq!(first_snapshot.included_diffs, vec![references[1].clone()]);
+
+        let second_snapshot = snapshot_of(&references[3]).expect("snapshot after four commits");
+        assert_eq!(second_snapshot.included_diffs, vec![references[3].clone()]);
+
+        let rendered = render::<MockPerspectiveGraph>()
+            .expect("render does not error")
+            .links
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        assert_eq!(rendered, links.into_iter().collect::<BTreeSet<_>>());
+    }
+
+    #[test]
+    fn commit_enforces_spam_limit() {
+        reset_mocked_graph();
+
+        let properties = DnaProperties {
+            enforce_spam_limit: 2,
+            ..test_properties()
+        };
+        for i in 0..2 {
+            commit::<MockPerspectiveGraph>(
+                PerspectiveDiff {
+                    additions: vec![create_link_expression("a", &format!("{}", i))],
+                    removals: vec![],
+                },
+                &properties,
+            )
+            .expect("commits within the limit do not error");
+        }
+
+        let result = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![create_link_expression("a", "2")],
+                removals: vec![],
+            },
+            &properties,
+        );
+        assert!(matches!(
+            result,
+            Err(SocialContextError::SpamLimitExceeded { limit: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn commit_stores_metadata() {
+        reset_mocked_graph();
+
+        let properties = test_properties();
+        let metadata = CommitMetadata {
+            author: Some(String::from("did:test:alice")),
+            message: Some(String::from("Import contacts")),
+            tags: vec![String::from("import")],
+            client_version: Some(String::from("0.2.0")),
+        };
+        let hash = commit_with_metadata::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![create_link_expression("a", "1")],
+                removals: vec![],
+            },
+            Some(metadata.clone()),
+            &properties,
+        )
+        .unwrap();
+
+        let reference = MockPerspectiveGraph::get::<PerspectiveDiffEntryReference>(hash).unwrap();
+        assert_eq!(reference.metadata, Some(metadata));
+    }
+
+    #[test]
+    fn references_without_metadata_deserialize() {
+        #[derive(Serialize, Deserialize, SerializedBytes, Debug)]
+        struct LegacyReference {
+            diff: ActionHash,
+            parents: Option<Vec<ActionHash>>,
+            diffs_since_snapshot: usize,
+        }
+
+        let legacy = LegacyReference {
+            diff: ActionHash::from_raw_36(vec![0xdb; 36]),
+            parents: None,
+            diffs_since_snapshot: 1,
+        };
+        let bytes = SerializedBytes::try_from(legacy).unwrap();
+        let reference = PerspectiveDiffEntryReference::try_from(bytes).unwrap();
+        assert_eq!(reference.diffs_since_snapshot, 1);
+        assert_eq!(reference.metadata, None);
+    }
+
+    #[test]
+    fn commit_chunks_large_diffs() {
+        reset_mocked_graph();
+
+        let properties = DnaProperties {
+            chunk_size: 2,
+            ..test_properties()
+        };
+        let links = (0..5)
+            .map(|i| create_link_expression("a", &format!("{}", i)))
+            .collect::<Vec<_>>();
+        let hash = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: links.clone(),
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+
+        let reference = MockPerspectiveGraph::get::<PerspectiveDiffEntryReference>(hash).unwrap();
+        let chunks = reference.diff_chunks.clone().unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(reference.diff, chunks[0]);
+        assert_eq!(
+            get_reference_diff::<MockPerspectiveGraph>(&reference)
+                .unwrap()
+                .additions,
+            links
+        );
+
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![],
+                removals: links[0..3].to_vec(),
+            },
+            &properties,
+        )
+        .unwrap();
+        assert_eq!(
+            render::<MockPerspectiveGraph>().unwrap().links,
+            links[3..5].to_vec()
+        );
+    }
+
+    #[test]
+    fn commit_stores_compact_diffs() {
+        reset_mocked_graph();
+
+        let properties = DnaProperties {
+            snapshot_interval: 2,
+            diff_encoding: DiffEncoding::CompactCompressed,
+            ..test_properties()
+        };
+        let links = (0..4)
+            .map(|i| create_link_expression("a", &format!("{}", i)))
+            .collect::<Vec<_>>();
+        let mut hashes = vec![];
+        for link in links.iter() {
+            hashes.push(
+                commit::<MockPerspectiveGraph>(
+                    PerspectiveDiff {
+                        additions: vec![link.clone()],
+                        removals: vec![],
+                    },
+                    &properties,
+                )
+                .unwrap(),
+            );
+        }
+
+        //Snapshot chunks are written with the same encoding
+        create_pending_snapshots::<MockPerspectiveGraph>(&properties).unwrap();
+
+        let reference =
+            MockPerspectiveGraph::get::<PerspectiveDiffEntryReference>(hashes[0].clone()).unwrap();
+        let compact =
+            MockPerspectiveGraph::get::<CompactPerspectiveDiff>(reference.diff.clone()).unwrap();
+        assert!(compact.compressed);
+        assert_eq!(
+            get_reference_diff::<MockPerspectiveGraph>(&reference)
+                .unwrap()
+                .additions,
+            vec![links[0].clone()]
+        );
+
+        //Plain and compact diffs can be mixed within the same history
+        let plain = DnaProperties {
+            diff_encoding: DiffEncoding::Plain,
+            ..properties.clone()
+        };
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![],
+                removals: vec![links[0].clone()],
+            },
+            &plain,
+        )
+        .unwrap();
+        *PERSPECTIVE_CACHE.lock().unwrap() = None;
+        assert_eq!(
+            render::<MockPerspectiveGraph>().unwrap().links,
+            links[1..4].to_vec()
+        );
+    }
+
+    #[test]
+    fn commit_rejects_links_violating_the_schema() {
+        reset_mocked_graph();
+
+        let properties = DnaProperties {
+            link_schema: Some(LinkSchema {
+                require_predicate: true,
+                ..LinkSchema::default()
+            }),
+            ..test_properties()
+        };
+        let mut valid = create_link_expression("a", "1");
+        valid.data.predicate = Some(String::from("rdf://name"));
+        let invalid = create_link_expression("a", "2");
+
+        let result = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![valid.clone(), invalid.clone()],
+                removals: vec![],
+            },
+            &properties,
+        );
+        match result {
+            Err(SocialContextError::SchemaViolation { violations }) => {
+                assert_eq!(violations.len(), 1);
+                assert_eq!(violations[0].link, invalid);
+            }
+            other => panic!("Expected a schema violation, got {:?}", other),
+        }
+        assert!(CURRENT_REVISION.lock().unwrap().is_none());
+
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![valid],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+    }
+}
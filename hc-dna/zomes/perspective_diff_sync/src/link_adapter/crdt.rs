@@ -0,0 +1,170 @@
+use perspective_diff_sync_integrity::properties::MergePolicy;
+use perspective_diff_sync_integrity::{
+    LinkExpression, PerspectiveDiff, PerspectiveDiffEntryReference,
+};
+use std::collections::BTreeMap;
+
+use crate::errors::SocialContextResult;
+use crate::link_adapter::chunked_diffs::get_reference_diff;
+use crate::retriever::PerspectiveDiffRetreiver;
+use crate::Hash;
+
+/// Net effect of a sequence of diffs: whether each link touched ends up added (true) or removed (false)
+pub type LinkChanges = BTreeMap<LinkExpression, bool>;
+
+pub struct ResolvedMerge {
+    /// Stored as the diff of the merge entry, so replaying both branches and then the merge
+    /// always ends in the resolved state, no matter in which order the branches were replayed
+    pub merge_diff: PerspectiveDiff,
+    /// What has to be applied on top of our branch to arrive at the resolved state
+    pub theirs_diff: PerspectiveDiff,
+}
+
+pub fn apply_diff(changes: &mut LinkChanges, diff: PerspectiveDiff) {
+    for addition in diff.additions {
+        changes.insert(addition, true);
+    }
+    for removal in diff.removals {
+        changes.insert(removal, false);
+    }
+}
+
+/// Replays the given diff references, which have to be in topological order.
+pub fn net_changes<Retriever: PerspectiveDiffRetreiver>(
+    references: &[(Hash, PerspectiveDiffEntryReference)],
+) -> SocialContextResult<LinkChanges> {
+    let mut changes = LinkChanges::new();
+    for (_hash, reference) in references.iter() {
+        apply_diff(&mut changes, get_reference_diff::<Retriever>(reference)?);
+    }
+    Ok(changes)
+}
+
+pub fn into_diff(changes: LinkChanges) -> PerspectiveDiff {
+    let mut diff = PerspectiveDiff::new();
+    for (link, is_addition) in changes {
+        if is_addition {
+            diff.additions.push(link);
+        } else {
+            diff.removals.push(link);
+        }
+    }
+    diff
+}
+
+/// Merges the changes of two concurrent branches as an observed-remove set.
+/// Links only touched by one branch keep that branch's change; links added on one branch and
+/// removed on the other are resolved by the merge policy.
+pub fn resolve_merge(
+    ours: &LinkChanges,
+    theirs: &LinkChanges,
+    policy: &MergePolicy,
+) -> ResolvedMerge {
+    let add_wins = policy == &MergePolicy::AddWins;
+    let mut merge_changes = LinkChanges::new();
+    let mut theirs_changes = LinkChanges::new();
+
+    for (link, is_addition) in theirs.iter() {
+        match ours.get(link) {
+            Some(ours_is_addition) if ours_is_addition != is_addition => {
+                merge_changes.insert(link.clone(), add_wins);
+                //We already are in the winning state if our change won
+                if *is_addition == add_wins {
+                    theirs_changes.insert(link.clone(), *is_addition);
+                }
+            }
+            _ => {
+                theirs_changes.insert(link.clone(), *is_addition);
+            }
+        }
+    }
+
+    ResolvedMerge {
+        merge_diff: into_diff(merge_changes),
+        theirs_diff: into_diff(theirs_changes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve_merge, LinkChanges};
+    use crate::utils::create_link_expression;
+    use perspective_diff_sync_integrity::properties::MergePolicy;
+
+    fn changes(changes: Vec<(&str, bool)>) -> LinkChanges {
+        changes
+            .into_iter()
+            .map(|(target, is_addition)| (create_link_expression("a", target), is_addition))
+            .collect()
+    }
+
+    #[test]
+    fn add_wins_resolves_concurrent_add_and_remove() {
+        let ours = changes(vec![("1", true), ("2", false), ("3", true)]);
+        let theirs = changes(vec![("1", false), ("2", true), ("4", true)]);
+
+        let resolved = resolve_merge(&ours, &theirs, &MergePolicy::AddWins);
+        assert_eq!(
+            resolved.merge_diff.additions,
+            vec![
+                create_link_expression("a", "1"),
+                create_link_expression("a", "2")
+            ]
+        );
+        assert!(resolved.merge_diff.removals.is_empty());
+
+        //Their removal of 1 lost, their addition of 2 won
+        assert_eq!(
+            resolved.theirs_diff.additions,
+            vec![
+                create_link_expression("a", "2"),
+                create_link_expression("a", "4")
+            ]
+        );
+        assert!(resolved.theirs_diff.removals.is_empty());
+    }
+
+    #[test]
+    fn remove_wins_resolves_concurrent_add_and_remove() {
+        let ours = changes(vec![("1", true), ("2", false)]);
+        let theirs = changes(vec![("1", false), ("2", true), ("3", false)]);
+
+        let resolved = resolve_merge(&ours, &theirs, &MergePolicy::RemoveWins);
+        assert!(resolved.merge_diff.additions.is_empty());
+        assert_eq!(
+            resolved.merge_diff.removals,
+            vec![
+                create_link_expression("a", "1"),
+                create_link_expression("a", "2")
+            ]
+        );
+
+        assert!(resolved.theirs_diff.additions.is_empty());
+        assert_eq!(
+            resolved.theirs_diff.removals,
+            vec![
+                create_link_expression("a", "1"),
+                create_link_expression("a", "3")
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_without_conflicts_are_empty() {
+        let ours = changes(vec![("1", true), ("2", true)]);
+        let theirs = changes(vec![("2", true), ("3", false)]);
+
+        for policy in [MergePolicy::AddWins, MergePolicy::RemoveWins].iter() {
+            let resolved = resolve_merge(&ours, &theirs, policy);
+            assert_eq!(resolved.merge_diff.total_diff_number(), 0);
+            assert_eq!(
+                resolved.theirs_diff.additions,
+                vec![create_link_expression("a", "2")]
+            );
+            assert_eq!(
+                resolved.theirs_diff.removals,
+                vec![create_link_expression("a", "3")]
+            );
+        }
+    }
+}
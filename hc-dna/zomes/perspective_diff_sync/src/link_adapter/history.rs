@@ -0,0 +1,181 @@
+use perspective_diff_sync_integrity::{
+    PerspectiveDiff, PerspectiveDiffEntryReference, RevisionHistoryEntry,
+};
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+
+use crate::errors::SocialContextResult;
+use crate::link_adapter::render::render_at;
+use crate::link_adapter::revisions::current_revision;
+use crate::retriever::PerspectiveDiffRetreiver;
+use crate::Hash;
+
+/// Returns up to limit revisions reachable from `from` (or our current revision), newest first.
+/// Concurrent branches are interleaved by the time their entries were created.
+pub fn get_revision_history<Retriever: PerspectiveDiffRetreiver>(
+    from: Option<Hash>,
+    limit: usize,
+) -> SocialContextResult<Vec<RevisionHistoryEntry>> {
+    let from = match from {
+        Some(from) => from,
+        None => match current_revision::<Retriever>()? {
+            Some(current) => current.hash,
+            None => return Ok(vec![]),
+        },
+    };
+
+    let mut history = vec![];
+    let mut visited = BTreeSet::new();
+    let mut references = BTreeMap::new();
+    let mut next = BinaryHeap::new();
+    visited.insert(from.clone());
+    let (reference, timestamp) =
+        Retriever::get_with_timestamp::<PerspectiveDiffEntryReference>(from.clone())?;
+    references.insert(from.clone(), reference);
+    next.push((timestamp, from));
+
+    while history.len() < limit {
+        let (timestamp, hash) = match next.pop() {
+            Some(entry) => entry,
+            None => break,
+        };
+        let reference = references
+            .remove(&hash)
+            .expect("references are stored before being queued");
+        for parent in reference.parents.iter().flatten() {
+            if visited.insert(parent.clone()) {
+                let (parent_reference, parent_timestamp) =
+                    Retriever::get_with_timestamp::<PerspectiveDiffEntryReference>(parent.clone())?;
+                references.insert(parent.clone(), parent_reference);
+                next.push((parent_timestamp, parent.clone()));
+            }
+        }
+        history.push(RevisionHistoryEntry {
+            author: Retriever::get_author(hash.clone())?,
+            hash,
+            reference,
+            timestamp,
+        });
+    }
+
+    Ok(history)
+}
+
+/// Returns the diff that turns the perspective at `from` into the perspective at `to`.
+///
+/// Replaying the diffs only one side has seen is not enough, since links changed on the side of
+/// `from` but not on the side of `to` have to go back to the state of their common ancestors.
+/// So both revisions are rendered, which uses the render cache where possible.
+pub fn diff_between<Retriever: PerspectiveDiffRetreiver>(
+    from: Hash,
+    to: Hash,
+) -> SocialContextResult<PerspectiveDiff> {
+    if from == to {
+        return Ok(PerspectiveDiff::new());
+    }
+
+    let from_links = render_at::<Retriever>(from)?
+        .links
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+    let to_links = render_at::<Retriever>(to)?
+        .links
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+
+    Ok(PerspectiveDiff {
+        additions: to_links.difference(&from_links).cloned().collect(),
+        removals: from_links.difference(&to_links).cloned().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use perspective_diff_sync_integrity::PerspectiveDiff;
+
+    use super::{diff_between, get_revision_history};
+    use crate::link_adapter::commit::commit;
+    use crate::link_adapter::tests::{reset_mocked_graph, test_properties};
+    use crate::retriever::{MockPerspectiveGraph, PerspectiveDiffRetreiver};
+    use crate::utils::create_link_expression;
+
+    #[test]
+    fn lists_history_newest_first() {
+        reset_mocked_graph();
+        let mut commits = vec![];
+        for target in ["1", "2", "3"].iter() {
+            commits.push(
+                commit::<MockPerspectiveGraph>(
+                    PerspectiveDiff {
+                        additions: vec![create_link_expression("a", target)],
+                        removals: vec![],
+                    },
+                    &test_properties(),
+                )
+                .unwrap(),
+            );
+        }
+
+        let history = get_revision_history::<MockPerspectiveGraph>(None, 10).unwrap();
+        assert_eq!(
+            history
+                .iter()
+                .map(|entry| entry.hash.clone())
+                .collect::<Vec<_>>(),
+            commits.iter().rev().cloned().collect::<Vec<_>>()
+        );
+
+        let history =
+            get_revision_history::<MockPerspectiveGraph>(Some(commits[1].clone()), 1).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].hash, commits[1]);
+        assert_eq!(history[0].reference.parents, Some(vec![commits[0].clone()]));
+    }
+
+    #[test]
+    fn diffs_between_revisions() {
+        reset_mocked_graph();
+        let a1 = create_link_expression("a", "1");
+        let a2 = create_link_expression("a", "2");
+        let a3 = create_link_expression("a", "3");
+
+        let base = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![a1.clone()],
+                removals: vec![],
+            },
+            &test_properties(),
+        )
+        .unwrap();
+        let left = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![a2.clone()],
+                removals: vec![a1.clone()],
+            },
+            &test_properties(),
+        )
+        .unwrap();
+        MockPerspectiveGraph::update_current_revision(base.clone(), chrono::Utc::now()).unwrap();
+        let right = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![a3.clone()],
+                removals: vec![],
+            },
+            &test_properties(),
+        )
+        .unwrap();
+
+        let diff = diff_between::<MockPerspectiveGraph>(base.clone(), left.clone()).unwrap();
+        assert_eq!(diff.additions, vec![a2.clone()]);
+        assert_eq!(diff.removals, vec![a1.clone()]);
+
+        let diff = diff_between::<MockPerspectiveGraph>(left.clone(), base).unwrap();
+        assert_eq!(diff.additions, vec![a1.clone()]);
+        assert_eq!(diff.removals, vec![a2.clone()]);
+
+        //Going across the fork reverts the left branch and applies the right one
+        //a1 was removed on the left branch only, so it is back at the right one
+        let diff = diff_between::<MockPerspectiveGraph>(left, right).unwrap();
+        assert_eq!(diff.additions, vec![a1, a3]);
+        assert_eq!(diff.removals, vec![a2]);
+    }
+}
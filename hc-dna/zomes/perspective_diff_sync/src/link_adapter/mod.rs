@@ -1,8 +1,14 @@
 pub(crate) mod chunked_diffs;
 pub(crate) mod commit;
+pub(crate) mod crdt;
+pub(crate) mod history;
+pub(crate) mod moderation;
 pub(crate) mod pull;
+pub(crate) mod query;
 pub(crate) mod render;
+pub(crate) mod revert;
 pub(crate) mod revisions;
+pub(crate) mod scribe;
 pub(crate) mod snapshots;
 pub(crate) mod test_graphs;
 pub(crate) mod tests;
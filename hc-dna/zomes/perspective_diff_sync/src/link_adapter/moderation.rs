@@ -0,0 +1,300 @@
+use chrono::{DateTime, Utc};
+use hdk::prelude::*;
+use perspective_diff_sync_integrity::moderation::BanList;
+use perspective_diff_sync_integrity::{
+    Anchor, EntryTypes, LinkExpression, LinkTypes, PerspectiveDiff, PerspectiveDiffEntryReference,
+};
+use std::collections::BTreeSet;
+
+use crate::errors::SocialContextResult;
+use crate::link_adapter::render::collect_links;
+use crate::retriever::PerspectiveDiffRetreiver;
+use crate::Hash;
+
+fn get_moderation_anchor() -> Anchor {
+    Anchor("moderation".to_string())
+}
+
+/// Stores a ban list signed by an admin, which replaces the previous one from its timestamp on
+pub fn issue_ban_list<Retriever: PerspectiveDiffRetreiver>(
+    ban_list: BanList,
+) -> SocialContextResult<Hash> {
+    let anchor = get_moderation_anchor();
+    Retriever::create_entry(EntryTypes::Anchor(anchor.clone()))?;
+    let ban_list_hash = Retriever::create_entry(EntryTypes::BanList(ban_list))?;
+    Retriever::create_link(
+        Retriever::hash_entry(anchor)?.into(),
+        ban_list_hash.clone().into(),
+        LinkTypes::BanList,
+        LinkTag::new("ban_list"),
+    )?;
+    Ok(ban_list_hash)
+}
+
+/// The ban list in effect at the given time, if any got issued before it
+pub fn ban_list_at<Retriever: PerspectiveDiffRetreiver>(
+    time: DateTime<Utc>,
+) -> SocialContextResult<Option<BanList>> {
+    let mut ban_lists = vec![];
+    for target in Retriever::get_link_targets(
+        Retriever::hash_entry(get_moderation_anchor())?.into(),
+        LinkTypes::BanList,
+        None,
+    )? {
+        if let Some(hash) = target.into_action_hash() {
+            ban_lists.push(Retriever::get::<BanList>(hash)?);
+        }
+    }
+    Ok(ban_lists
+        .into_iter()
+        .filter(|ban_list| ban_list.timestamp <= time)
+        .max_by(|a, b| a.timestamp.cmp(&b.timestamp)))
+}
+
+/// DIDs banned as of the given revision, judged by the time the revision was created
+pub fn banned_at<Retriever: PerspectiveDiffRetreiver>(
+    revision: Hash,
+) -> SocialContextResult<BTreeSet<String>> {
+    let (_reference, created) =
+        Retriever::get_with_timestamp::<PerspectiveDiffEntryReference>(revision)?;
+    Ok(ban_list_at::<Retriever>(created)?
+        .map(|ban_list| ban_list.data.banned)
+        .unwrap_or_default())
+}
+
+pub fn retract_banned<I>(links: I, banned: &BTreeSet<String>) -> Vec<LinkExpression>
+where
+    I: IntoIterator<Item = LinkExpression>,
+{
+    links
+        .into_iter()
+        .filter(|link| !banned.contains(&link.author))
+        .collect()
+}
+
+pub fn retract_banned_diff(diff: PerspectiveDiff, banned: &BTreeSet<String>) -> PerspectiveDiff {
+    PerspectiveDiff {
+        additions: retract_banned(diff.additions, banned),
+        removals: retract_banned(diff.removals, banned),
+    }
+}
+
+/// Changes to the perspective at revision `from` when judging it by `banned` instead of the ban
+/// list in effect at `from`: links of newly banned authors get removed, links of authors whose
+/// ban got lifted get added back
+pub fn ban_list_changes<Retriever: PerspectiveDiffRetreiver>(
+    from: Hash,
+    banned: &BTreeSet<String>,
+) -> SocialContextResult<PerspectiveDiff> {
+    let banned_before = banned_at::<Retriever>(from.clone())?;
+    let mut changes = PerspectiveDiff::new();
+    if banned_before == *banned {
+        return Ok(changes);
+    }
+    for link in collect_links::<Retriever>(from)? {
+        match (
+            banned_before.contains(&link.author),
+            banned.contains(&link.author),
+        ) {
+            (false, true) => changes.removals.push(link),
+            (true, false) => changes.additions.push(link),
+            _ => {}
+        }
+    }
+    Ok(changes)
+}
+
+/// Moderates a diff which moves the perspective on from revision `from` to a revision judged by
+/// `banned`. Besides retracting links of banned authors from the diff, this catches up on ban
+/// lists issued in between, which change what the perspective at `from` showed.
+pub fn moderate_diff<Retriever: PerspectiveDiffRetreiver>(
+    diff: PerspectiveDiff,
+    from: Hash,
+    banned: &BTreeSet<String>,
+) -> SocialContextResult<PerspectiveDiff> {
+    let changes = ban_list_changes::<Retriever>(from, banned)?;
+    let mut diff = retract_banned_diff(diff, banned);
+    let removals = &diff.removals;
+    let additions = changes
+        .additions
+        .into_iter()
+        .filter(|link| !removals.contains(link))
+        .collect::<Vec<LinkExpression>>();
+    diff.additions.extend(additions);
+    diff.removals.extend(changes.removals);
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use perspective_diff_sync_integrity::moderation::{BanList, BanListData};
+    use perspective_diff_sync_integrity::properties::DnaProperties;
+    use perspective_diff_sync_integrity::{ExpressionProof, LinkExpression, PerspectiveDiff};
+
+    use super::{ban_list_changes, banned_at, issue_ban_list};
+    use crate::link_adapter::chunked_diffs::ChunkedDiffs;
+    use crate::link_adapter::commit::commit;
+    use crate::link_adapter::pull::pull;
+    use crate::link_adapter::render::{collect_links, render};
+    use crate::link_adapter::snapshots::{create_pending_snapshots, generate_snapshot};
+    use crate::link_adapter::tests::{reset_mocked_graph, test_properties};
+    use crate::retriever::{MockPerspectiveGraph, PerspectiveDiffRetreiver};
+    use crate::utils::create_link_expression;
+
+    fn properties() -> DnaProperties {
+        DnaProperties {
+            snapshot_interval: 1,
+            ..test_properties()
+        }
+    }
+
+    fn link_by(author: &str, target: &str) -> LinkExpression {
+        let mut link = create_link_expression("a", target);
+        link.author = String::from(author);
+        link
+    }
+
+    fn ban(banned: &[&str], minutes_ago: i64) {
+        issue_ban_list::<MockPerspectiveGraph>(BanList {
+            author: String::from("did:test:admin"),
+            data: BanListData {
+                banned: banned.iter().map(|did| String::from(*did)).collect(),
+            },
+            timestamp: Utc::now() - Duration::minutes(minutes_ago),
+            proof: ExpressionProof {
+                signature: String::from("sig"),
+                key: String::from("key"),
+            },
+            author_membership: None,
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn render_retracts_links_of_banned_authors() {
+        reset_mocked_graph();
+        let properties = properties();
+        let alice = link_by("did:test:alice", "1");
+        let mallory = link_by("did:test:mallory", "2");
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![alice.clone(), mallory.clone()],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+        create_pending_snapshots::<MockPerspectiveGraph>(&properties).unwrap();
+
+        ban(&["did:test:mallory"], 2);
+        //Ban lists only take effect from their timestamp on
+        ban(&[], -60);
+        assert_eq!(
+            render::<MockPerspectiveGraph>().unwrap().links,
+            vec![alice.clone()]
+        );
+
+        //Lifting the ban brings the links back, even though the perspective got cached
+        ban(&[], 1);
+        assert_eq!(
+            render::<MockPerspectiveGraph>().unwrap().links,
+            vec![alice, mallory]
+        );
+    }
+
+    #[test]
+    fn pull_retracts_links_of_banned_authors() {
+        reset_mocked_graph();
+        let properties = properties();
+        let base = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![create_link_expression("a", "base")],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+        let alice = link_by("did:test:alice", "1");
+        let theirs = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![alice.clone(), link_by("did:test:mallory", "2")],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+        MockPerspectiveGraph::update_current_revision(base, Utc::now()).unwrap();
+        ban(&["did:test:mallory"], 1);
+
+        let pulled = pull::<MockPerspectiveGraph>(false, theirs, true, true, &properties).unwrap();
+        assert_eq!(pulled.diff.additions, vec![alice]);
+        assert!(pulled.diff.removals.is_empty());
+    }
+
+    #[test]
+    fn snapshots_keep_links_of_banned_authors() {
+        reset_mocked_graph();
+        let properties = properties();
+        let alice = link_by("did:test:alice", "1");
+        let mallory = link_by("did:test:mallory", "2");
+        let latest = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![alice.clone(), mallory.clone()],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+        ban(&["did:test:mallory"], 1);
+
+        //Bans can be lifted, so snapshots keep everything and rendering retracts banned links
+        let links = collect_links::<MockPerspectiveGraph>(latest.clone()).unwrap();
+        let snapshot =
+            generate_snapshot::<MockPerspectiveGraph>(latest, links, &properties).unwrap();
+        let diff = ChunkedDiffs::from_entries::<MockPerspectiveGraph>(
+            snapshot.diff_chunks,
+            properties.chunk_size,
+        )
+        .unwrap()
+        .into_aggregated_diff();
+        assert_eq!(diff.additions.len(), 2);
+        assert!(diff.additions.contains(&mallory));
+        assert_eq!(render::<MockPerspectiveGraph>().unwrap().links, vec![alice]);
+    }
+
+    #[test]
+    fn ban_list_changes_update_the_shown_perspective() {
+        reset_mocked_graph();
+        let properties = properties();
+        let alice = link_by("did:test:alice", "1");
+        let mallory = link_by("did:test:mallory", "2");
+        let before = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![alice.clone(), mallory.clone()],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+        ban(&["did:test:mallory"], 0);
+        let after = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![link_by("did:test:alice", "3")],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+
+        let banned = banned_at::<MockPerspectiveGraph>(after.clone()).unwrap();
+        let changes = ban_list_changes::<MockPerspectiveGraph>(before, &banned).unwrap();
+        assert!(changes.additions.is_empty());
+        assert_eq!(changes.removals, vec![mallory.clone()]);
+
+        //Lifting the ban again brings the link back
+        let changes = ban_list_changes::<MockPerspectiveGraph>(after, &Default::default()).unwrap();
+        assert_eq!(changes.additions, vec![mallory]);
+        assert!(changes.removals.is_empty());
+    }
+}
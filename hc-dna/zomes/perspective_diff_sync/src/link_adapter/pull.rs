@@ -1,39 +1,55 @@
 use hdk::prelude::*;
+use perspective_diff_sync_integrity::properties::DnaProperties;
 use perspective_diff_sync_integrity::{
     EntryTypes, HashBroadcast, PerspectiveDiff, PerspectiveDiffEntryReference, PullResult,
 };
 
 use crate::errors::SocialContextResult;
+use crate::link_adapter::chunked_diffs::{create_reference_diff, get_reference_diff};
+use crate::link_adapter::commit::within_commit_rate;
+use crate::link_adapter::crdt::{net_changes, resolve_merge};
+use crate::link_adapter::moderation::{ban_list_at, banned_at, moderate_diff, retract_banned_diff};
 use crate::link_adapter::revisions::{current_revision, update_current_revision};
+use crate::link_adapter::scribe::peer_seen;
 use crate::link_adapter::workspace::{Workspace, NULL_NODE};
 use crate::retriever::PerspectiveDiffRetreiver;
 use crate::utils::get_now;
 use crate::Hash;
 
+/// Creates a merge entry on top of latest and current.
+///
+/// With deterministic merges the parents are sorted, so any agent merging the same two revisions
+/// creates the same entry content (the merge diff only depends on the two branches). Since action
+/// hashes still differ between authors, such merges are considered equal by `equivalent_merges`,
+/// also once other commits got built on top of one of them.
 fn merge<Retriever: PerspectiveDiffRetreiver>(
     latest: Hash,
     current: Hash,
+    merge_diff: PerspectiveDiff,
+    properties: &DnaProperties,
 ) -> SocialContextResult<Hash> {
     debug!("===PerspectiveDiffSync.merge(): Function start");
     let fn_start = get_now()?.time();
 
     let latest_diff = Retriever::get::<PerspectiveDiffEntryReference>(latest.clone())?;
     let current_diff = Retriever::get::<PerspectiveDiffEntryReference>(current.clone())?;
-    //Create the merge diff
-    let merge_diff = PerspectiveDiff {
-        additions: vec![],
-        removals: vec![],
-    };
-    let merge_entry_hash =
-        Retriever::create_entry(EntryTypes::PerspectiveDiff(merge_diff.clone()))?;
+    //Create the merge diff, containing the resolution of conflicting changes on both branches
+    let (merge_entry_hash, diff_chunks) =
+        create_reference_diff::<Retriever>(merge_diff.clone(), properties)?;
 
+    let mut parents = vec![latest, current];
+    if properties.deterministic_merges {
+        parents.sort();
+    }
     //Create the merge entry reference
     let merge_entry_reference = PerspectiveDiffEntryReference {
-        parents: Some(vec![latest, current]),
+        parents: Some(parents),
         diff: merge_entry_hash.clone(),
         diffs_since_snapshot: latest_diff.diffs_since_snapshot
             + current_diff.diffs_since_snapshot
             + 1,
+        metadata: None,
+        diff_chunks,
     };
     let merge_entry_reference_hash = Retriever::create_entry(
         EntryTypes::PerspectiveDiffEntryReference(merge_entry_reference.clone()),
@@ -54,10 +70,55 @@ fn merge<Retriever: PerspectiveDiffRetreiver>(
     Ok(merge_entry_reference_hash)
 }
 
+/// Two merges are equivalent if they merge the same revisions with the same diff, which is the case
+/// for merges created independently by different agents with deterministic merges.
+fn equivalent_merges<Retriever: PerspectiveDiffRetreiver>(
+    a: &Hash,
+    b: &Hash,
+) -> SocialContextResult<bool> {
+    let a = Retriever::get::<PerspectiveDiffEntryReference>(a.clone())?;
+    let b = Retriever::get::<PerspectiveDiffEntryReference>(b.clone())?;
+    let (mut a_parents, mut b_parents) = match (a.parents.clone(), b.parents.clone()) {
+        (Some(a_parents), Some(b_parents)) if a_parents.len() > 1 => (a_parents, b_parents),
+        _ => return Ok(false),
+    };
+    a_parents.sort();
+    b_parents.sort();
+    if a_parents != b_parents || a.diffs_since_snapshot != b.diffs_since_snapshot {
+        return Ok(false);
+    }
+    if a.diff == b.diff {
+        return Ok(true);
+    }
+    let a_diff = get_reference_diff::<Retriever>(&a)?;
+    let b_diff = get_reference_diff::<Retriever>(&b)?;
+    Ok(a_diff.additions == b_diff.additions && a_diff.removals == b_diff.removals)
+}
+
+/// Whether head is or descends from a merge equivalent to the given merge, i.e. another agent
+/// merged the same revisions and possibly committed on top of its merge already
+fn descends_from_equivalent_merge<Retriever: PerspectiveDiffRetreiver>(
+    workspace: &Workspace,
+    head: &Hash,
+    merge: &Hash,
+) -> SocialContextResult<bool> {
+    for ancestor in workspace.all_ancestors(head)? {
+        if ancestor == NULL_NODE() || &ancestor == merge {
+            continue;
+        }
+        if equivalent_merges::<Retriever>(&ancestor, merge)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
 pub fn pull<Retriever: PerspectiveDiffRetreiver>(
     emit: bool,
     theirs: Hash,
     is_scribe: bool,
+    can_write: bool,
+    properties: &DnaProperties,
 ) -> SocialContextResult<PullResult> {
     debug!("===PerspectiveDiffSync.pull(): Function start");
     let fn_start = get_now()?.time();
@@ -82,7 +143,10 @@ pub fn pull<Retriever: PerspectiveDiffRetreiver>(
 
     if current.is_none() {
         workspace.collect_only_from_latest::<Retriever>(theirs.clone())?;
-        let diff = workspace.squashed_diff::<Retriever>()?;
+        let diff = retract_banned_diff(
+            workspace.squashed_diff::<Retriever>()?,
+            &banned_at::<Retriever>(theirs.clone())?,
+        );
         update_current_revision::<Retriever>(theirs, get_now()?)?;
         emit_signal(diff.clone())?;
         return Ok(PullResult {
@@ -92,24 +156,50 @@ pub fn pull<Retriever: PerspectiveDiffRetreiver>(
     }
 
     let current = current.expect("current missing handled above");
+    let previous = current.hash.clone();
+
+    //Another agent merged the same revisions as we did, so both sides have the same perspective.
+    //Everyone settles on the smaller of the equivalent merges to stop merging back and forth.
+    if properties.deterministic_merges && equivalent_merges::<Retriever>(&theirs, &current.hash)? {
+        let converged = if theirs < current.hash {
+            update_current_revision::<Retriever>(theirs.clone(), get_now()?)?;
+            theirs
+        } else {
+            current.hash
+        };
+        return Ok(PullResult {
+            diff: PerspectiveDiff::default(),
+            current_revision: Some(converged),
+        });
+    }
 
     workspace.build_diffs::<Retriever>(theirs.clone(), current.hash.clone())?;
 
     // First check if we are actually ahead of them -> we don't have to do anything
     // they will have to merge with / or fast-forward to our current
-    if workspace.all_ancestors(&current.hash)?.contains(&theirs) {
+    if workspace.all_ancestors(&current.hash)?.contains(&theirs)
+        || (properties.deterministic_merges
+            && descends_from_equivalent_merge::<Retriever>(&workspace, &current.hash, &theirs)?)
+    {
         return Ok(PullResult {
             diff: PerspectiveDiff::default(),
             current_revision: Some(current.hash),
         });
     }
 
-    let fast_forward_possible = workspace.all_ancestors(&theirs)?.contains(&current.hash);
+    //Their history containing a merge equivalent to ours is as good as containing ours
+    let fast_forward_possible = workspace.all_ancestors(&theirs)?.contains(&current.hash)
+        || (properties.deterministic_merges
+            && descends_from_equivalent_merge::<Retriever>(&workspace, &theirs, &current.hash)?);
 
     // If we can't fast forward, we have to merge
-    // but if we are not a scribe, we can't merge
-    // so in that case, we can't do anything
-    if !fast_forward_possible && !is_scribe {
+    // but if we are not a scribe, we can't merge unless merges are deterministic
+    // so in that case, we can't do anything. Merging is committing, which needs a writing role
+    // and a commit left within the spam limit, otherwise validation would reject the merge.
+    let can_merge = can_write
+        && (is_scribe || properties.deterministic_merges)
+        && within_commit_rate::<Retriever>(properties)?;
+    if !fast_forward_possible && !can_merge {
         debug!("===PerspectiveDiffSync.pull(): Have to merge but I'm not a scribe. Exiting without change...");
         return Ok(PullResult {
             diff: PerspectiveDiff::default(),
@@ -122,7 +212,7 @@ pub fn pull<Retriever: PerspectiveDiffRetreiver>(
     // println!("SEEN DIFFS: {:#?}", seen_diffs);
 
     //Get all the diffs in the graph which we havent seen
-    let unseen_diffs = if seen_diffs.len() > 0 {
+    let unseen_diffs = if !seen_diffs.is_empty() {
         let diffs = workspace
             .sorted_diffs
             .clone()
@@ -145,6 +235,7 @@ pub fn pull<Retriever: PerspectiveDiffRetreiver>(
     } else {
         workspace
             .sorted_diffs
+            .clone()
             .expect("should be unseen diffs after build_diffs() call")
             .into_iter()
             .filter(|val| val.0 != NULL_NODE() && val.0 != current.hash)
@@ -158,7 +249,7 @@ pub fn pull<Retriever: PerspectiveDiffRetreiver>(
             removals: vec![],
         };
         for diff in unseen_diffs {
-            let diff_entry = Retriever::get::<PerspectiveDiff>(diff.1.diff.clone())?;
+            let diff_entry = get_reference_diff::<Retriever>(&diff.1)?;
             out.additions.append(&mut diff_entry.additions.clone());
             out.removals.append(&mut diff_entry.removals.clone());
         }
@@ -169,20 +260,30 @@ pub fn pull<Retriever: PerspectiveDiffRetreiver>(
             (fn_end - fn_start).num_milliseconds()
         );
         (out, theirs)
-    } else if is_scribe {
+    } else if can_merge {
         debug!("===PerspectiveDiffSync.pull():There are no paths between current and latest, we must merge current and latest");
-        //Get the entries we missed from unseen diff
-        let mut out = PerspectiveDiff {
-            additions: vec![],
-            removals: vec![],
-        };
-        for diff in unseen_diffs {
-            let diff_entry = Retriever::get::<PerspectiveDiff>(diff.1.diff.clone())?;
-            out.additions.append(&mut diff_entry.additions.clone());
-            out.removals.append(&mut diff_entry.removals.clone());
-        }
+        //Get the diffs only we have seen, so we can detect links both branches changed
+        let theirs_ancestors = workspace.all_ancestors(&theirs)?;
+        let ours_diffs = workspace
+            .sorted_diffs
+            .clone()
+            .expect("should be sorted diffs after build_diffs() call")
+            .into_iter()
+            .filter(|val| {
+                val.0 != NULL_NODE()
+                    && seen_diffs.contains(&val.0)
+                    && !theirs_ancestors.contains(&val.0)
+            })
+            .collect::<Vec<(Hash, PerspectiveDiffEntryReference)>>();
 
-        let merge_hash = merge::<Retriever>(theirs, current.hash)?;
+        let resolved = resolve_merge(
+            &net_changes::<Retriever>(&ours_diffs)?,
+            &net_changes::<Retriever>(&unseen_diffs)?,
+            &properties.merge_policy,
+        );
+
+        let merge_hash = merge::<Retriever>(theirs, current.hash, resolved.merge_diff, properties)?;
+        let out = resolved.theirs_diff;
         let fn_end = get_now()?.time();
         debug!(
             "===PerspectiveDiffSync.pull() - Profiling: Took: {} to complete pull() function",
@@ -199,9 +300,16 @@ pub fn pull<Retriever: PerspectiveDiffRetreiver>(
         )
     };
 
+    //So far the perspective got judged by the ban list in effect at our previous revision
+    let diffs = moderate_diff::<Retriever>(
+        diffs,
+        previous,
+        &banned_at::<Retriever>(current_revision.clone())?,
+    )?;
+
     //Emit the signal in case the client connection has a timeout during the zome call
     if emit {
-        if diffs.additions.len() > 0 || diffs.removals.len() > 0 {
+        if !diffs.additions.is_empty() || !diffs.removals.is_empty() {
             emit_signal(diffs.clone())?;
         }
     }
@@ -211,9 +319,16 @@ pub fn pull<Retriever: PerspectiveDiffRetreiver>(
     })
 }
 
+/// Fast forwards to a broadcasted revision if possible. `sender_did` is the DID the agent key of
+/// the sender is linked to, only then the broadcast counts as a sign of life of its author.
 pub fn handle_broadcast<Retriever: PerspectiveDiffRetreiver>(
     broadcast: HashBroadcast,
+    sender_did: Option<String>,
+    properties: &DnaProperties,
 ) -> SocialContextResult<()> {
+    if sender_did.as_ref() == Some(&broadcast.broadcast_author) {
+        peer_seen::<Retriever>(broadcast.broadcast_author.clone(), get_now()?, properties)?;
+    }
     // debug!("===PerspectiveDiffSync.fast_forward_signal(): Function start");
     // let fn_start = get_now()?.time();
     let diff_reference = broadcast.reference.clone();
@@ -226,10 +341,18 @@ pub fn handle_broadcast<Retriever: PerspectiveDiffRetreiver>(
         if revision == current_revision.hash {
             // debug!("===PerspectiveDiffSync.fast_forward_signal(): Revision is the same as current");
         };
-        if diff_reference.parents == Some(vec![current_revision.hash]) {
+        if diff_reference.parents == Some(vec![current_revision.hash.clone()]) {
             // debug!("===PerspectiveDiffSync.fast_forward_signal(): Revisions parent is the same as current, we can fast forward our current");
             update_current_revision::<Retriever>(revision, get_now()?)?;
-            emit_signal(broadcast.diff.clone())?;
+            //The broadcasted revision was just created, so the ban list in effect now applies
+            let banned = ban_list_at::<Retriever>(get_now()?)?
+                .map(|ban_list| ban_list.data.banned)
+                .unwrap_or_default();
+            emit_signal(moderate_diff::<Retriever>(
+                broadcast_changes::<Retriever>(&broadcast)?,
+                current_revision.hash,
+                &banned,
+            )?)?;
         };
     };
     emit_signal(broadcast)?;
@@ -238,15 +361,35 @@ pub fn handle_broadcast<Retriever: PerspectiveDiffRetreiver>(
     Ok(())
 }
 
+/// The changes of a broadcasted revision, fetching the chunks of diffs too large to be sent along
+pub fn broadcast_changes<Retriever: PerspectiveDiffRetreiver>(
+    broadcast: &HashBroadcast,
+) -> SocialContextResult<PerspectiveDiff> {
+    match broadcast.reference.diff_chunks {
+        Some(_) => get_reference_diff::<Retriever>(&broadcast.reference),
+        None => Ok(broadcast.diff.clone()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::pull;
+    use super::{broadcast_changes, pull};
+    use crate::link_adapter::chunked_diffs::get_reference_diff;
+    use crate::link_adapter::commit::{broadcast_diff, commit};
+    use crate::link_adapter::render::render;
+    use crate::link_adapter::tests::{reset_mocked_graph, test_properties};
     use crate::retriever::{
         create_node_id_link_expression, create_node_id_vec, node_id_hash, MockPerspectiveGraph,
-        PerspectiveDiffRetreiver, GLOBAL_MOCKED_GRAPH,
+        PerspectiveDiffRetreiver, GLOBAL_MOCKED_GRAPH, MOCK_AGENT,
     };
     use crate::utils::create_link_expression;
+    use crate::Hash;
     use dot_structures;
+    use hdk::prelude::{ActionHash, AgentPubKey};
+    use perspective_diff_sync_integrity::properties::{DiffEncoding, DnaProperties, MergePolicy};
+    use perspective_diff_sync_integrity::{
+        HashBroadcast, LinkExpression, PerspectiveDiff, PerspectiveDiffEntryReference,
+    };
 
     #[test]
     fn test_fast_forward_merge() {
@@ -277,7 +420,13 @@ mod tests {
             MockPerspectiveGraph::update_current_revision(current_node_hash, chrono::Utc::now());
         assert!(update_current.is_ok());
 
-        let pull_res = pull::<MockPerspectiveGraph>(false, latest_node_hash, true);
+        let pull_res = pull::<MockPerspectiveGraph>(
+            false,
+            latest_node_hash,
+            true,
+            true,
+            &DnaProperties::default(),
+        );
         assert!(pull_res.is_ok());
         let pull_res = pull_res.unwrap();
 
@@ -326,7 +475,13 @@ mod tests {
             MockPerspectiveGraph::update_current_revision(current_node_hash, chrono::Utc::now());
         assert!(update_current.is_ok());
 
-        let pull_res = pull::<MockPerspectiveGraph>(false, latest_node_hash.clone(), true);
+        let pull_res = pull::<MockPerspectiveGraph>(
+            false,
+            latest_node_hash.clone(),
+            true,
+            true,
+            &DnaProperties::default(),
+        );
         assert!(pull_res.is_ok());
         let pull_res = pull_res.unwrap();
 
@@ -388,7 +543,13 @@ mod tests {
             MockPerspectiveGraph::update_current_revision(current_node_hash, chrono::Utc::now());
         assert!(update_current.is_ok());
 
-        let pull_res = pull::<MockPerspectiveGraph>(false, latest_node_hash, true);
+        let pull_res = pull::<MockPerspectiveGraph>(
+            false,
+            latest_node_hash,
+            true,
+            true,
+            &DnaProperties::default(),
+        );
         assert!(pull_res.is_ok());
         let pull_res = pull_res.unwrap();
 
@@ -442,7 +603,13 @@ mod tests {
             MockPerspectiveGraph::update_current_revision(current_node_hash, chrono::Utc::now());
         assert!(update_current.is_ok());
 
-        let pull_res = pull::<MockPerspectiveGraph>(false, latest_node_hash, true);
+        let pull_res = pull::<MockPerspectiveGraph>(
+            false,
+            latest_node_hash,
+            true,
+            true,
+            &DnaProperties::default(),
+        );
         assert!(pull_res.is_ok());
         let pull_res = pull_res.unwrap();
 
@@ -497,7 +664,13 @@ mod tests {
         let node_1 = &node_id_hash(&dot_structures::Id::Plain(String::from("1"))).to_string();
         let expected_additions = vec![create_link_expression(node_1, node_1)];
 
-        let pull_res = pull::<MockPerspectiveGraph>(false, latest_node_hash.clone(), true);
+        let pull_res = pull::<MockPerspectiveGraph>(
+            false,
+            latest_node_hash.clone(),
+            true,
+            true,
+            &DnaProperties::default(),
+        );
         assert!(pull_res.is_ok());
         assert!(pull_res
             .unwrap()
@@ -558,7 +731,13 @@ mod tests {
             create_link_expression(node_2, node_2),
         ];
 
-        let pull_res = pull::<MockPerspectiveGraph>(false, latest_node_hash.clone(), true);
+        let pull_res = pull::<MockPerspectiveGraph>(
+            false,
+            latest_node_hash.clone(),
+            true,
+            true,
+            &DnaProperties::default(),
+        );
         assert!(pull_res.is_ok());
         assert!(pull_res
             .unwrap()
@@ -603,7 +782,13 @@ mod tests {
         );
         assert!(update_current.is_ok());
 
-        let pull_res = pull::<MockPerspectiveGraph>(false, latest_node_hash.clone(), true);
+        let pull_res = pull::<MockPerspectiveGraph>(
+            false,
+            latest_node_hash.clone(),
+            true,
+            true,
+            &DnaProperties::default(),
+        );
         assert!(pull_res.is_ok());
         println!("{:#?}", pull_res);
         let pull_res = pull_res.unwrap();
@@ -662,7 +847,13 @@ mod tests {
         );
         assert!(update_current.is_ok());
 
-        let pull_res = pull::<MockPerspectiveGraph>(false, latest_node_hash.clone(), true);
+        let pull_res = pull::<MockPerspectiveGraph>(
+            false,
+            latest_node_hash.clone(),
+            true,
+            true,
+            &DnaProperties::default(),
+        );
         assert!(pull_res.is_ok());
         println!("{:#?}", pull_res);
         let pull_res = pull_res.unwrap();
@@ -711,7 +902,13 @@ mod tests {
         );
         assert!(update_current.is_ok());
 
-        let pull_res = pull::<MockPerspectiveGraph>(false, latest_node_hash.clone(), true);
+        let pull_res = pull::<MockPerspectiveGraph>(
+            false,
+            latest_node_hash.clone(),
+            true,
+            true,
+            &DnaProperties::default(),
+        );
         assert!(pull_res.is_ok());
         //println!("{:#?}", pull_res);
         let pull_res = pull_res.unwrap();
@@ -752,7 +949,13 @@ mod tests {
         );
         assert!(update_current.is_ok());
 
-        let pull_res = pull::<MockPerspectiveGraph>(false, latest_node_hash.clone(), true);
+        let pull_res = pull::<MockPerspectiveGraph>(
+            false,
+            latest_node_hash.clone(),
+            true,
+            true,
+            &DnaProperties::default(),
+        );
         assert!(pull_res.is_ok());
         //println!("{:#?}", pull_res);
         let pull_res = pull_res.unwrap();
@@ -788,7 +991,13 @@ mod tests {
         );
         assert!(update_current.is_ok());
 
-        let pull_res = pull::<MockPerspectiveGraph>(false, latest_node_hash.clone(), true);
+        let pull_res = pull::<MockPerspectiveGraph>(
+            false,
+            latest_node_hash.clone(),
+            true,
+            true,
+            &DnaProperties::default(),
+        );
         assert!(pull_res.is_ok());
         let pull_res = pull_res.unwrap();
 
@@ -827,7 +1036,13 @@ mod tests {
         );
         assert!(update_current.is_ok());
 
-        let pull_res = pull::<MockPerspectiveGraph>(false, latest_node_hash.clone(), true);
+        let pull_res = pull::<MockPerspectiveGraph>(
+            false,
+            latest_node_hash.clone(),
+            true,
+            true,
+            &DnaProperties::default(),
+        );
         assert!(pull_res.is_ok());
         let pull_res = pull_res.unwrap();
 
@@ -843,4 +1058,444 @@ mod tests {
         let current = MockPerspectiveGraph::current_revision();
         assert!(current.unwrap().unwrap().hash != current_node_hash);
     }
+
+    fn merge_concurrent_add_and_remove(
+        policy: MergePolicy,
+    ) -> (PerspectiveDiff, PerspectiveDiff, Vec<LinkExpression>) {
+        reset_mocked_graph();
+
+        let properties = DnaProperties {
+            merge_policy: policy,
+            ..test_properties()
+        };
+        let contested = create_link_expression("a", "contested");
+        let ours_link = create_link_expression("a", "ours");
+        let theirs_link = create_link_expression("a", "theirs");
+
+        let base = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![contested.clone()],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+        //Their branch removes the link, while ours removes and adds it again
+        let theirs = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![theirs_link],
+                removals: vec![contested.clone()],
+            },
+            &properties,
+        )
+        .unwrap();
+        MockPerspectiveGraph::update_current_revision(base, chrono::Utc::now()).unwrap();
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![ours_link],
+                removals: vec![contested.clone()],
+            },
+            &properties,
+        )
+        .unwrap();
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![contested],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+
+        let pull_res =
+            pull::<MockPerspectiveGraph>(false, theirs, true, true, &properties).unwrap();
+        let merge = MockPerspectiveGraph::get::<PerspectiveDiffEntryReference>(
+            pull_res.current_revision.unwrap(),
+        )
+        .unwrap();
+        let merge_diff = MockPerspectiveGraph::get::<PerspectiveDiff>(merge.diff).unwrap();
+        let rendered = render::<MockPerspectiveGraph>().unwrap().links;
+        (pull_res.diff, merge_diff, rendered)
+    }
+
+    #[test]
+    fn chunked_broadcasts_leave_the_diff_to_fetch() {
+        reset_mocked_graph();
+
+        let properties = DnaProperties {
+            chunk_size: 2,
+            ..test_properties()
+        };
+        let links = (0..5)
+            .map(|i| create_link_expression("a", &format!("{}", i)))
+            .collect::<Vec<_>>();
+        let hash = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: links.clone(),
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+        let reference =
+            MockPerspectiveGraph::get::<PerspectiveDiffEntryReference>(hash.clone()).unwrap();
+
+        let broadcast = HashBroadcast {
+            diff: broadcast_diff::<MockPerspectiveGraph>(&reference).unwrap(),
+            reference,
+            reference_hash: hash,
+            broadcast_author: String::from("did:test:alice"),
+        };
+        assert_eq!(broadcast.diff.total_diff_number(), 0);
+        assert_eq!(
+            broadcast_changes::<MockPerspectiveGraph>(&broadcast)
+                .unwrap()
+                .additions,
+            links
+        );
+    }
+
+    #[test]
+    fn chunks_merges_larger_than_chunk_size() {
+        reset_mocked_graph();
+
+        let properties = DnaProperties {
+            chunk_size: 2,
+            diff_encoding: DiffEncoding::CompactCompressed,
+            ..test_properties()
+        };
+        let contested = (0..5)
+            .map(|i| create_link_expression("a", &format!("{}", i)))
+            .collect::<Vec<_>>();
+        let base = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: contested.clone(),
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+        let theirs = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![],
+                removals: contested.clone(),
+            },
+            &properties,
+        )
+        .unwrap();
+        MockPerspectiveGraph::update_current_revision(base, chrono::Utc::now()).unwrap();
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![],
+                removals: contested.clone(),
+            },
+            &properties,
+        )
+        .unwrap();
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: contested.clone(),
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+
+        //Every contested link gets added again by the merge, more than fit into one entry
+        let pull_res =
+            pull::<MockPerspectiveGraph>(false, theirs, true, true, &properties).unwrap();
+        let merge = MockPerspectiveGraph::get::<PerspectiveDiffEntryReference>(
+            pull_res.current_revision.unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            merge.diff_chunks.as_ref().map(|chunks| chunks.len()),
+            Some(3)
+        );
+        assert_eq!(
+            get_reference_diff::<MockPerspectiveGraph>(&merge)
+                .unwrap()
+                .additions,
+            contested
+        );
+        assert_eq!(render::<MockPerspectiveGraph>().unwrap().links, contested);
+    }
+
+    #[test]
+    fn merges_only_within_the_spam_limit() {
+        for (limit, merges) in [(4, false), (5, true)] {
+            reset_mocked_graph();
+
+            let properties = DnaProperties {
+                enforce_spam_limit: limit,
+                ..test_properties()
+            };
+            let base = commit::<MockPerspectiveGraph>(
+                PerspectiveDiff {
+                    additions: vec![create_link_expression("a", "base")],
+                    removals: vec![],
+                },
+                &properties,
+            )
+            .unwrap();
+            let theirs = commit::<MockPerspectiveGraph>(
+                PerspectiveDiff {
+                    additions: vec![create_link_expression("a", "theirs")],
+                    removals: vec![],
+                },
+                &properties,
+            )
+            .unwrap();
+            MockPerspectiveGraph::update_current_revision(base, chrono::Utc::now()).unwrap();
+            let mut ours = None;
+            for i in 0..2 {
+                ours = Some(
+                    commit::<MockPerspectiveGraph>(
+                        PerspectiveDiff {
+                            additions: vec![create_link_expression("a", &format!("{}", i))],
+                            removals: vec![],
+                        },
+                        &properties,
+                    )
+                    .unwrap(),
+                );
+            }
+
+            //The merge would be the fifth commit within the window
+            let pull_res =
+                pull::<MockPerspectiveGraph>(false, theirs, true, true, &properties).unwrap();
+            assert_eq!(pull_res.current_revision != ours, merges);
+            assert_eq!(pull_res.diff.additions.is_empty(), !merges);
+        }
+    }
+
+    #[test]
+    fn test_merge_add_wins() {
+        let contested = create_link_expression("a", "contested");
+        let (pulled, merge_diff, rendered) = merge_concurrent_add_and_remove(MergePolicy::AddWins);
+
+        assert_eq!(merge_diff.additions, vec![contested.clone()]);
+        assert!(merge_diff.removals.is_empty());
+        //Their removal lost, so we only have to add their other link
+        assert_eq!(
+            pulled.additions,
+            vec![create_link_expression("a", "theirs")]
+        );
+        assert!(pulled.removals.is_empty());
+        assert_eq!(
+            rendered,
+            vec![
+                contested,
+                create_link_expression("a", "ours"),
+                create_link_expression("a", "theirs")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_remove_wins() {
+        let contested = create_link_expression("a", "contested");
+        let (pulled, merge_diff, rendered) =
+            merge_concurrent_add_and_remove(MergePolicy::RemoveWins);
+
+        assert!(merge_diff.additions.is_empty());
+        assert_eq!(merge_diff.removals, vec![contested.clone()]);
+        assert_eq!(
+            pulled.additions,
+            vec![create_link_expression("a", "theirs")]
+        );
+        assert_eq!(pulled.removals, vec![contested]);
+        assert_eq!(
+            rendered,
+            vec![
+                create_link_expression("a", "ours"),
+                create_link_expression("a", "theirs")
+            ]
+        );
+    }
+
+    fn deterministic_properties() -> DnaProperties {
+        DnaProperties {
+            deterministic_merges: true,
+            ..test_properties()
+        }
+    }
+
+    /// Commits two concurrent branches on top of a common base, returning their heads
+    fn concurrent_branches(properties: &DnaProperties) -> (Hash, Hash) {
+        reset_mocked_graph();
+
+        let base = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![create_link_expression("a", "base")],
+                removals: vec![],
+            },
+            properties,
+        )
+        .unwrap();
+        let left = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![create_link_expression("a", "left")],
+                removals: vec![],
+            },
+            properties,
+        )
+        .unwrap();
+        MockPerspectiveGraph::update_current_revision(base, chrono::Utc::now()).unwrap();
+        let right = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![create_link_expression("a", "right")],
+                removals: vec![],
+            },
+            properties,
+        )
+        .unwrap();
+        (left, right)
+    }
+
+    #[test]
+    fn test_deterministic_merges_by_any_agent_converge() {
+        let properties = deterministic_properties();
+        let (left, right) = concurrent_branches(&properties);
+
+        //Agent on the right branch merges in left, without being the scribe
+        let pull_res =
+            pull::<MockPerspectiveGraph>(false, left.clone(), false, true, &properties).unwrap();
+        assert_eq!(
+            pull_res.diff.additions,
+            vec![create_link_expression("a", "left")]
+        );
+        let right_merge = pull_res.current_revision.unwrap();
+
+        //Agent on the left branch merges in right and arrives at the very same merge entry
+        MockPerspectiveGraph::update_current_revision(left, chrono::Utc::now()).unwrap();
+        let pull_res =
+            pull::<MockPerspectiveGraph>(false, right, false, true, &properties).unwrap();
+        assert_eq!(
+            pull_res.diff.additions,
+            vec![create_link_expression("a", "right")]
+        );
+        assert_eq!(pull_res.current_revision, Some(right_merge));
+    }
+
+    #[test]
+    fn test_deterministic_merges_adopt_equivalent_merge() {
+        let properties = deterministic_properties();
+        let (left, _right) = concurrent_branches(&properties);
+
+        let ours = pull::<MockPerspectiveGraph>(false, left, false, true, &properties)
+            .unwrap()
+            .current_revision
+            .unwrap();
+
+        //Another agent created the same merge, which ends up under a different hash on the DHT
+        let theirs = ActionHash::from_raw_36(vec![0; 36]);
+        {
+            let mut graph = GLOBAL_MOCKED_GRAPH.lock().unwrap();
+            let merge = graph.graph_map.get(&ours).unwrap().clone();
+            graph.graph_map.insert(theirs.clone(), merge);
+        }
+
+        let pull_res =
+            pull::<MockPerspectiveGraph>(false, theirs.clone(), false, true, &properties).unwrap();
+        assert_eq!(pull_res.diff.total_diff_number(), 0);
+        assert_eq!(pull_res.current_revision, Some(theirs.clone()));
+        assert_eq!(
+            MockPerspectiveGraph::current_revision()
+                .unwrap()
+                .unwrap()
+                .hash,
+            theirs
+        );
+
+        //Pulling our own merge now keeps the smaller one instead of merging again
+        let pull_res = pull::<MockPerspectiveGraph>(false, ours, false, true, &properties).unwrap();
+        assert_eq!(pull_res.current_revision, Some(theirs));
+    }
+
+    #[test]
+    fn test_deterministic_merges_by_distinct_authors_converge() {
+        let properties = deterministic_properties();
+        let (left, right) = concurrent_branches(&properties);
+        let alice = AgentPubKey::from_raw_36(vec![0xa1; 36]);
+        let bob = AgentPubKey::from_raw_36(vec![0xb0; 36]);
+
+        //Alice is on the right branch and merges in left
+        *MOCK_AGENT.lock().unwrap() = alice.clone();
+        let alice_merge =
+            pull::<MockPerspectiveGraph>(false, left.clone(), false, true, &properties)
+                .unwrap()
+                .current_revision
+                .unwrap();
+
+        //Bob is on the left branch and merges in right, the same merge by another author
+        *MOCK_AGENT.lock().unwrap() = bob;
+        MockPerspectiveGraph::update_current_revision(left, chrono::Utc::now()).unwrap();
+        let bob_merge = pull::<MockPerspectiveGraph>(false, right, false, true, &properties)
+            .unwrap()
+            .current_revision
+            .unwrap();
+        assert_ne!(alice_merge, bob_merge);
+
+        //Both settle on the smaller merge without merging again
+        let pull_res =
+            pull::<MockPerspectiveGraph>(false, alice_merge.clone(), false, true, &properties)
+                .unwrap();
+        assert_eq!(pull_res.diff.total_diff_number(), 0);
+        assert_eq!(
+            pull_res.current_revision,
+            Some(std::cmp::min(alice_merge.clone(), bob_merge.clone()))
+        );
+
+        //Alice commits on top of her merge before she hears of Bob's
+        *MOCK_AGENT.lock().unwrap() = alice;
+        MockPerspectiveGraph::update_current_revision(alice_merge, chrono::Utc::now()).unwrap();
+        let on_top = create_link_expression("a", "on top");
+        let alice_head = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![on_top.clone()],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+
+        //Alice is ahead of Bob's merge
+        let pull_res =
+            pull::<MockPerspectiveGraph>(false, bob_merge.clone(), false, true, &properties)
+                .unwrap();
+        assert_eq!(pull_res.diff.total_diff_number(), 0);
+        assert_eq!(pull_res.current_revision, Some(alice_head.clone()));
+
+        //Bob fast forwards to Alice's commit instead of merging it with his equivalent merge
+        MockPerspectiveGraph::update_current_revision(bob_merge, chrono::Utc::now()).unwrap();
+        let pull_res =
+            pull::<MockPerspectiveGraph>(false, alice_head.clone(), false, true, &properties)
+                .unwrap();
+        assert!(pull_res.diff.additions.contains(&on_top));
+        assert_eq!(pull_res.current_revision, Some(alice_head));
+    }
+
+    #[test]
+    fn test_non_deterministic_merges_need_scribe() {
+        let properties = DnaProperties {
+            deterministic_merges: false,
+            ..deterministic_properties()
+        };
+        let (left, right) = concurrent_branches(&properties);
+
+        let pull_res = pull::<MockPerspectiveGraph>(false, left, false, true, &properties).unwrap();
+        assert_eq!(pull_res.diff.total_diff_number(), 0);
+        assert_eq!(pull_res.current_revision, Some(right));
+    }
+
+    #[test]
+    fn test_read_only_members_never_merge() {
+        let properties = deterministic_properties();
+        let (left, right) = concurrent_branches(&properties);
+
+        let pull_res = pull::<MockPerspectiveGraph>(false, left, true, false, &properties).unwrap();
+        assert_eq!(pull_res.diff.total_diff_number(), 0);
+        assert_eq!(pull_res.current_revision, Some(right));
+    }
 }
@@ -0,0 +1,275 @@
+use perspective_diff_sync_integrity::{LinkExpression, LinkIndex, LocalPerspectiveCache};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::errors::{SocialContextError, SocialContextResult};
+use crate::inputs::LinkQuery;
+use crate::link_adapter::crdt::LinkChanges;
+use crate::link_adapter::moderation::banned_at;
+use crate::link_adapter::render::cache_and_changes;
+use crate::link_adapter::revisions::current_revision;
+use crate::retriever::PerspectiveDiffRetreiver;
+
+/// Indexes links by source, predicate and target. Built whenever the render cache moves to a
+/// new revision and stored with it, so queries don't have to index the perspective themselves.
+pub fn build_index(links: &[LinkExpression]) -> LinkIndex {
+    let mut index = LinkIndex::default();
+    let add = |map: &mut BTreeMap<String, Vec<u32>>, value: &Option<String>, position: usize| {
+        if let Some(value) = value {
+            map.entry(value.clone()).or_default().push(position as u32);
+        }
+    };
+    for (position, link) in links.iter().enumerate() {
+        add(&mut index.by_source, &link.data.source, position);
+        add(&mut index.by_predicate, &link.data.predicate, position);
+        add(&mut index.by_target, &link.data.target, position);
+    }
+    index
+}
+
+/// Positions of the links that can match the query, taken from the smallest index it selects.
+/// None if the query sets none of the indexed fields.
+fn lookup<'a>(index: &'a LinkIndex, query: &LinkQuery) -> Option<&'a [u32]> {
+    let lookups = [
+        (&query.source, &index.by_source),
+        (&query.predicate, &index.by_predicate),
+        (&query.target, &index.by_target),
+    ];
+    let mut candidates: Option<&[u32]> = None;
+    for (value, map) in lookups.iter().copied() {
+        if let Some(value) = value {
+            let positions = map.get(value).map_or(&[][..], |positions| &positions[..]);
+            if candidates
+                .iter()
+                .all(|current| positions.len() < current.len())
+            {
+                candidates = Some(positions);
+            }
+        }
+    }
+    candidates
+}
+
+fn matches(link: &LinkExpression, query: &LinkQuery) -> bool {
+    let matches_field = |expected: &Option<String>, actual: &Option<String>| {
+        expected.is_none() || expected == actual
+    };
+    matches_field(&query.source, &link.data.source)
+        && matches_field(&query.predicate, &link.data.predicate)
+        && matches_field(&query.target, &link.data.target)
+        && query.author.iter().all(|author| author == &link.author)
+        && query.from_date.iter().all(|from| &link.timestamp >= from)
+        && query
+            .until_date
+            .iter()
+            .all(|until| &link.timestamp <= until)
+}
+
+/// Returns the links matching every given part of the query, in perspective order.
+/// The cached links are looked up through the index of the cache, only the links changed since
+/// the cache and queries on author or time alone need a scan.
+pub fn query_cache(
+    cache: &LocalPerspectiveCache,
+    changes: &LinkChanges,
+    banned: &BTreeSet<String>,
+    query: &LinkQuery,
+) -> Vec<LinkExpression> {
+    let candidates: Vec<&LinkExpression> =
+        match cache.index.as_ref().and_then(|index| lookup(index, query)) {
+            Some(positions) => positions
+                .iter()
+                .filter_map(|position| cache.links.get(*position as usize))
+                .collect(),
+            None => cache.links.iter().collect(),
+        };
+    let added = changes
+        .iter()
+        .filter(|(_link, is_addition)| **is_addition)
+        .map(|(link, _)| link);
+    let mut links = candidates
+        .into_iter()
+        //A change replaces the cached state of the same link
+        .filter(|link| !changes.contains_key(*link))
+        .chain(added)
+        .filter(|link| !banned.contains(&link.author) && matches(link, query))
+        .cloned()
+        .collect::<Vec<LinkExpression>>();
+    links.sort();
+    links.truncate(query.limit.unwrap_or(usize::MAX));
+    links
+}
+
+pub fn query_links<Retriever: PerspectiveDiffRetreiver>(
+    query: LinkQuery,
+) -> SocialContextResult<Vec<LinkExpression>> {
+    let current = current_revision::<Retriever>()?.ok_or(SocialContextError::NoCurrentRevision)?;
+    let banned = banned_at::<Retriever>(current.hash.clone())?;
+    let (cache, changes) = cache_and_changes::<Retriever>(current.hash)?;
+    Ok(query_cache(&cache, &changes, &banned, &query))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveDateTime, Utc};
+    use hdk::prelude::*;
+    use perspective_diff_sync_integrity::properties::DnaProperties;
+    use perspective_diff_sync_integrity::{LinkExpression, LocalPerspectiveCache, PerspectiveDiff};
+    use std::collections::BTreeSet;
+
+    use super::{build_index, query_cache, query_links};
+    use crate::inputs::LinkQuery;
+    use crate::link_adapter::commit::commit;
+    use crate::link_adapter::crdt::LinkChanges;
+    use crate::link_adapter::snapshots::create_pending_snapshots;
+    use crate::link_adapter::tests::{reset_mocked_graph, test_properties};
+    use crate::retriever::{MockPerspectiveGraph, PerspectiveDiffRetreiver};
+    use crate::utils::create_link_expression;
+
+    fn cache_of(mut links: Vec<LinkExpression>, indexed: bool) -> LocalPerspectiveCache {
+        links.sort();
+        LocalPerspectiveCache {
+            revision: ActionHash::from_raw_36(vec![0xdb; 36]),
+            index: if indexed {
+                Some(build_index(&links))
+            } else {
+                None
+            },
+            links,
+        }
+    }
+
+    #[test]
+    fn queries_links_by_pattern() {
+        let mut liked = create_link_expression("alice", "post");
+        liked.data.predicate = Some(String::from("likes"));
+        liked.timestamp = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(100, 0), Utc);
+        let mut authored = create_link_expression("alice", "post");
+        authored.data.predicate = Some(String::from("authored"));
+        authored.author = String::from("did:test:alice");
+        let other = create_link_expression("bob", "post");
+        let links = vec![liked.clone(), authored.clone(), other.clone()];
+        let mut sorted = links.clone();
+        sorted.sort();
+
+        //Looking the links up through the index finds the same as scanning all of them
+        for indexed in [true, false] {
+            let cache = cache_of(links.clone(), indexed);
+            let query = |query: LinkQuery| {
+                query_cache(&cache, &LinkChanges::new(), &BTreeSet::new(), &query)
+            };
+            assert_eq!(
+                query(LinkQuery {
+                    target: Some(String::from("post")),
+                    ..LinkQuery::default()
+                }),
+                sorted
+            );
+            assert_eq!(
+                query(LinkQuery {
+                    source: Some(String::from("alice")),
+                    predicate: Some(String::from("authored")),
+                    ..LinkQuery::default()
+                }),
+                vec![authored.clone()]
+            );
+            assert_eq!(
+                query(LinkQuery {
+                    author: Some(String::from("did:test:alice")),
+                    ..LinkQuery::default()
+                }),
+                vec![authored.clone()]
+            );
+            assert_eq!(
+                query(LinkQuery {
+                    source: Some(String::from("alice")),
+                    from_date: Some(DateTime::<Utc>::from_utc(
+                        NaiveDateTime::from_timestamp(50, 0),
+                        Utc
+                    )),
+                    ..LinkQuery::default()
+                }),
+                vec![liked.clone()]
+            );
+            assert_eq!(
+                query(LinkQuery {
+                    target: Some(String::from("post")),
+                    limit: Some(1),
+                    until_date: Some(DateTime::<Utc>::from_utc(
+                        NaiveDateTime::from_timestamp(50, 0),
+                        Utc
+                    )),
+                    ..LinkQuery::default()
+                }),
+                vec![sorted.iter().find(|link| **link != liked).cloned().unwrap()]
+            );
+            assert!(query(LinkQuery {
+                source: Some(String::from("carol")),
+                ..LinkQuery::default()
+            })
+            .is_empty());
+        }
+    }
+
+    #[test]
+    fn queries_apply_changes_since_the_cache() {
+        let kept = create_link_expression("alice", "1");
+        let removed = create_link_expression("alice", "2");
+        let added = create_link_expression("alice", "3");
+        let cache = cache_of(vec![kept.clone(), removed.clone()], true);
+        let mut changes = LinkChanges::new();
+        changes.insert(removed, false);
+        changes.insert(added.clone(), true);
+        changes.insert(create_link_expression("bob", "4"), true);
+
+        let query = LinkQuery {
+            source: Some(String::from("alice")),
+            ..LinkQuery::default()
+        };
+        assert_eq!(
+            query_cache(&cache, &changes, &BTreeSet::new(), &query),
+            vec![kept, added]
+        );
+    }
+
+    #[test]
+    fn queries_the_current_perspective() {
+        reset_mocked_graph();
+        let properties = DnaProperties {
+            snapshot_interval: 1,
+            ..test_properties()
+        };
+        let a1 = create_link_expression("a", "1");
+        let a2 = create_link_expression("a", "2");
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![a1.clone(), create_link_expression("b", "1")],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+        create_pending_snapshots::<MockPerspectiveGraph>(&properties).unwrap();
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![a2.clone()],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+
+        let query = LinkQuery {
+            source: Some(String::from("a")),
+            ..LinkQuery::default()
+        };
+        let mut expected = vec![a1, a2];
+        expected.sort();
+        assert_eq!(
+            query_links::<MockPerspectiveGraph>(query).unwrap(),
+            expected
+        );
+        //The second commit is not part of the cache yet, so both the index and the changes count
+        let cache = MockPerspectiveGraph::perspective_cache().unwrap().unwrap();
+        assert!(cache.index.is_some());
+        assert_eq!(cache.links.len(), 2);
+    }
+}
@@ -1,37 +1,37 @@
 use hdk::prelude::*;
-use perspective_diff_sync_integrity::PerspectiveDiff;
+use perspective_diff_sync_integrity::properties::DnaProperties;
+use perspective_diff_sync_integrity::{
+    LinkExpression, LocalPerspectiveCache, PerspectiveDiff, PerspectiveDiffEntryReference,
+    RenderPage,
+};
+use std::collections::BTreeMap;
+use std::ops::Bound::{Excluded, Unbounded};
 
 use crate::errors::{SocialContextError, SocialContextResult};
+use crate::link_adapter::chunked_diffs::get_reference_diff;
+use crate::link_adapter::crdt::{net_changes, LinkChanges};
+use crate::link_adapter::moderation::{banned_at, retract_banned};
 use crate::link_adapter::revisions::current_revision;
+use crate::link_adapter::topo_sort::topo_sort_subgraph;
 use crate::link_adapter::workspace::Workspace;
 use crate::retriever::PerspectiveDiffRetreiver;
 use crate::utils::get_now;
-use crate::Perspective;
+use crate::{Hash, Perspective};
 
+/// Renders the perspective at our current revision. Rendering never writes to the source chain,
+/// the cache it builds on is only updated when a snapshot gets created.
 pub fn render<Retriever: PerspectiveDiffRetreiver>() -> SocialContextResult<Perspective> {
     debug!("===PerspectiveDiffSync.render(): Function start");
     let fn_start = get_now()?.time();
 
-    let current = current_revision::<Retriever>()?.ok_or(SocialContextError::InternalError(
-        "Can't render when we have no current revision",
-    ))?;
+    let current = current_revision::<Retriever>()?.ok_or(SocialContextError::NoCurrentRevision)?;
 
     debug!("===PerspectiveDiffSync.render(): current: {:?}", current);
 
-    let mut workspace = Workspace::new();
-    workspace.collect_only_from_latest::<Retriever>(current.hash)?;
-
-    let mut perspective = Perspective { links: vec![] };
-    for diff_node in workspace.entry_map {
-        let diff_entry = Retriever::get::<PerspectiveDiff>(diff_node.1.diff.clone())?;
-
-        for addition in diff_entry.additions {
-            perspective.links.push(addition);
-        }
-        for removal in diff_entry.removals {
-            perspective.links.retain(|l| l != &removal);
-        }
-    }
+    let links = collect_links::<Retriever>(current.hash.clone())?;
+    let perspective = Perspective {
+        links: retract_banned(links, &banned_at::<Retriever>(current.hash)?),
+    };
 
     let fn_end = get_now()?.time();
     debug!(
@@ -40,3 +40,385 @@ pub fn render<Retriever: PerspectiveDiffRetreiver>() -> SocialContextResult<Pers
     );
     Ok(perspective)
 }
+
+/// Renders the perspective as it was at the given revision, which does not have to be
+/// our current revision. Links of authors banned as of the revision are left out, like in render.
+pub fn render_at<Retriever: PerspectiveDiffRetreiver>(
+    revision: Hash,
+) -> SocialContextResult<Perspective> {
+    debug!(
+        "===PerspectiveDiffSync.render_at(): revision: {:?}",
+        revision
+    );
+    let links = collect_links::<Retriever>(revision.clone())?;
+    Ok(Perspective {
+        links: retract_banned(links, &banned_at::<Retriever>(revision)?),
+    })
+}
+
+/// Returns up to limit links of the current perspective that come after the cursor, in the
+/// order of the links themselves, so paging stays stable while new links get added.
+/// Pages are never larger than the chunk size used for snapshots.
+///
+/// Pages are read from the render cache merged with the net changes since, so a page only
+/// costs the diffs after the cache rather than a full render.
+pub fn render_page<Retriever: PerspectiveDiffRetreiver>(
+    cursor: Option<LinkExpression>,
+    limit: usize,
+    properties: &DnaProperties,
+) -> SocialContextResult<RenderPage> {
+    if limit == 0 {
+        return Err(SocialContextError::InvalidPageLimit);
+    }
+    let current = current_revision::<Retriever>()?.ok_or(SocialContextError::NoCurrentRevision)?;
+    let limit = limit.min(properties.chunk_size as usize);
+    let (cache, changes) = cache_and_changes::<Retriever>(current.hash.clone())?;
+    let cached = cache.links;
+    let banned = banned_at::<Retriever>(current.hash.clone())?;
+
+    let start = match &cursor {
+        Some(cursor) => cached.partition_point(|link| link <= cursor),
+        None => 0,
+    };
+    let mut cached = cached[start..].iter().peekable();
+    let mut changed = match &cursor {
+        Some(cursor) => changes.range((Excluded(cursor), Unbounded)),
+        None => changes.range::<LinkExpression, _>(..),
+    }
+    .peekable();
+
+    //Both are sorted, so walking them side by side yields the links in order
+    let mut page = vec![];
+    let mut next_cursor = None;
+    loop {
+        let next = match (cached.peek(), changed.peek()) {
+            (None, None) => break,
+            (Some(link), Some((changed_link, _))) if link < changed_link => cached.next().cloned(),
+            (Some(_), None) => cached.next().cloned(),
+            (link, Some((changed_link, is_addition))) => {
+                let next = if **is_addition {
+                    Some((*changed_link).clone())
+                } else {
+                    None
+                };
+                //The change replaces the cached state of the same link
+                if link == Some(changed_link) {
+                    cached.next();
+                }
+                changed.next();
+                next
+            }
+        };
+        match next {
+            Some(link) if !banned.contains(&link.author) => {
+                if page.len() == limit {
+                    next_cursor = page.last().cloned();
+                    break;
+                }
+                page.push(link);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(RenderPage {
+        links: page,
+        next_cursor,
+        revision: current.hash,
+    })
+}
+
+/// Returns the render cache and the net changes of the diffs after it, if the cache is part of
+/// the history of revision. Otherwise all links at revision, without an index, and no changes.
+pub(crate) fn cache_and_changes<Retriever: PerspectiveDiffRetreiver>(
+    revision: Hash,
+) -> SocialContextResult<(LocalPerspectiveCache, LinkChanges)> {
+    if let Some(cache) = Retriever::perspective_cache()? {
+        if let Some(references) =
+            collect_since_cache::<Retriever>(revision.clone(), &cache.revision)?
+        {
+            let changes = net_changes::<Retriever>(&topo_sort_subgraph(&references)?)?;
+            return Ok((cache, changes));
+        }
+    }
+    let links = collect_links::<Retriever>(revision.clone())?;
+    let cache = LocalPerspectiveCache {
+        revision,
+        links: links.into_iter().collect(),
+        index: None,
+    };
+    Ok((cache, LinkChanges::new()))
+}
+
+/// Returns the links at the given revision, including the ones of banned authors
+pub(crate) fn collect_links<Retriever: PerspectiveDiffRetreiver>(
+    revision: Hash,
+) -> SocialContextResult<BTreeSet<LinkExpression>> {
+    let cache = Retriever::perspective_cache()?;
+    let incremental = match &cache {
+        Some(cache) => collect_since_cache::<Retriever>(revision.clone(), &cache.revision)?,
+        None => None,
+    };
+
+    match (cache, incremental) {
+        (Some(cache), Some(references)) => {
+            debug!(
+                "===PerspectiveDiffSync.render(): Applying {} diffs on top of cached revision",
+                references.len()
+            );
+            let mut links = cache.links.into_iter().collect::<BTreeSet<_>>();
+            for (_hash, reference) in topo_sort_subgraph(&references)? {
+                apply_diff(&mut links, get_reference_diff::<Retriever>(&reference)?);
+            }
+            Ok(links)
+        }
+        _ => {
+            let mut workspace = Workspace::new();
+            workspace.collect_only_from_latest::<Retriever>(revision)?;
+
+            //Diffs have to be applied in causal order, otherwise a removal could be applied
+            //before the addition it removes
+            let mut links = BTreeSet::new();
+            for (_hash, reference) in topo_sort_subgraph(&workspace.entry_map)? {
+                apply_diff(&mut links, get_reference_diff::<Retriever>(&reference)?);
+            }
+            Ok(links)
+        }
+    }
+}
+
+fn apply_diff(links: &mut BTreeSet<LinkExpression>, diff: PerspectiveDiff) {
+    for addition in diff.additions {
+        links.insert(addition);
+    }
+    for removal in diff.removals.iter() {
+        links.remove(removal);
+    }
+}
+
+/// Walks back from latest and collects all diff references until the cached revision.
+/// Returns None if any branch ends without passing through the cached revision, since then we
+/// can't tell which of its diffs are already contained in the cache.
+fn collect_since_cache<Retriever: PerspectiveDiffRetreiver>(
+    latest: Hash,
+    cached: &Hash,
+) -> SocialContextResult<Option<BTreeMap<Hash, PerspectiveDiffEntryReference>>> {
+    let mut unprocessed = vec![latest];
+    let mut references = BTreeMap::new();
+
+    while let Some(current) = unprocessed.pop() {
+        if &current == cached || references.contains_key(&current) {
+            continue;
+        }
+        let reference = Retriever::get::<PerspectiveDiffEntryReference>(current.clone())?;
+        match &reference.parents {
+            Some(parents) => unprocessed.extend(parents.iter().cloned()),
+            None => return Ok(None),
+        };
+        references.insert(current, reference);
+    }
+
+    Ok(Some(references))
+}
+
+#[cfg(test)]
+mod tests {
+    use perspective_diff_sync_integrity::properties::DnaProperties;
+    use perspective_diff_sync_integrity::PerspectiveDiff;
+
+    use super::{render, render_at, render_page};
+    use crate::errors::SocialContextError;
+    use crate::link_adapter::commit::commit;
+    use crate::link_adapter::snapshots::create_pending_snapshots;
+    use crate::link_adapter::tests::{reset_mocked_graph, test_properties};
+    use crate::retriever::{MockPerspectiveGraph, PerspectiveDiffRetreiver};
+    use crate::utils::create_link_expression;
+
+    #[test]
+    fn renders_incrementally_from_snapshot_cache() {
+        reset_mocked_graph();
+
+        let properties = DnaProperties {
+            snapshot_interval: 2,
+            ..test_properties()
+        };
+        let a1 = create_link_expression("a", "1");
+        let a2 = create_link_expression("a", "2");
+        let a3 = create_link_expression("a", "3");
+
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![a1.clone()],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+        let second = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![a2.clone()],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+
+        //Rendering never writes the cache
+        let perspective = render::<MockPerspectiveGraph>().unwrap();
+        assert_eq!(perspective.links, vec![a1.clone(), a2.clone()]);
+        assert!(MockPerspectiveGraph::perspective_cache().unwrap().is_none());
+
+        create_pending_snapshots::<MockPerspectiveGraph>(&properties).unwrap();
+        let cache = MockPerspectiveGraph::perspective_cache().unwrap().unwrap();
+        assert_eq!(cache.revision, second);
+        assert_eq!(cache.links, vec![a1.clone(), a2.clone()]);
+
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![a3.clone()],
+                removals: vec![a1.clone()],
+            },
+            &properties,
+        )
+        .unwrap();
+
+        //The diff after the snapshot gets applied on top of the cache
+        let perspective = render::<MockPerspectiveGraph>().unwrap();
+        assert_eq!(perspective.links, vec![a2, a3]);
+        let cache = MockPerspectiveGraph::perspective_cache().unwrap().unwrap();
+        assert_eq!(cache.revision, second);
+    }
+
+    #[test]
+    fn renders_historical_revisions() {
+        reset_mocked_graph();
+
+        let properties = DnaProperties {
+            snapshot_interval: 2,
+            ..test_properties()
+        };
+        let a1 = create_link_expression("a", "1");
+        let a2 = create_link_expression("a", "2");
+
+        let first = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![a1.clone()],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+        let second = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![a2.clone()],
+                removals: vec![a1.clone()],
+            },
+            &properties,
+        )
+        .unwrap();
+        //Caches the current revision, which render_at can't build on for older revisions
+        create_pending_snapshots::<MockPerspectiveGraph>(&properties).unwrap();
+
+        assert_eq!(
+            render_at::<MockPerspectiveGraph>(first).unwrap().links,
+            vec![a1]
+        );
+        assert_eq!(
+            render_at::<MockPerspectiveGraph>(second.clone())
+                .unwrap()
+                .links,
+            vec![a2]
+        );
+        let cache = MockPerspectiveGraph::perspective_cache().unwrap().unwrap();
+        assert_eq!(cache.revision, second);
+    }
+
+    #[test]
+    fn renders_pages_after_cursor() {
+        reset_mocked_graph();
+
+        let properties = DnaProperties {
+            chunk_size: 3,
+            ..test_properties()
+        };
+        let links = (0..5)
+            .map(|i| create_link_expression("a", &format!("{}", i)))
+            .collect::<Vec<_>>();
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: links.clone(),
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+
+        let first = render_page::<MockPerspectiveGraph>(None, 2, &properties).unwrap();
+        assert_eq!(first.links, links[0..2].to_vec());
+        assert_eq!(first.next_cursor, Some(links[1].clone()));
+
+        //The limit is capped at the chunk size
+        let last = render_page::<MockPerspectiveGraph>(first.next_cursor, 10, &properties).unwrap();
+        assert_eq!(last.links, links[2..5].to_vec());
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[test]
+    fn rejects_pages_without_links() {
+        reset_mocked_graph();
+
+        let properties = test_properties();
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![create_link_expression("a", "1")],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            render_page::<MockPerspectiveGraph>(None, 0, &properties),
+            Err(SocialContextError::InvalidPageLimit)
+        ));
+    }
+
+    #[test]
+    fn renders_pages_from_cache_and_later_commits() {
+        reset_mocked_graph();
+
+        let properties = DnaProperties {
+            snapshot_interval: 1,
+            ..test_properties()
+        };
+        let links = (0..4)
+            .map(|i| create_link_expression("a", &format!("{}", i)))
+            .collect::<Vec<_>>();
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![links[0].clone(), links[2].clone()],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+        create_pending_snapshots::<MockPerspectiveGraph>(&properties).unwrap();
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![links[1].clone(), links[3].clone()],
+                removals: vec![links[2].clone()],
+            },
+            &properties,
+        )
+        .unwrap();
+
+        let first = render_page::<MockPerspectiveGraph>(None, 2, &properties).unwrap();
+        assert_eq!(first.links, links[0..2].to_vec());
+        assert_eq!(first.next_cursor, Some(links[1].clone()));
+
+        //The cached link removed after the snapshot is left out
+        let last = render_page::<MockPerspectiveGraph>(first.next_cursor, 2, &properties).unwrap();
+        assert_eq!(last.links, vec![links[3].clone()]);
+        assert_eq!(last.next_cursor, None);
+    }
+}
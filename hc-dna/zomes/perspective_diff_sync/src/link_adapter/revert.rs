@@ -0,0 +1,180 @@
+use perspective_diff_sync_integrity::properties::DnaProperties;
+use perspective_diff_sync_integrity::{
+    LinkExpression, PerspectiveDiff, PerspectiveDiffEntryReference,
+};
+use std::collections::BTreeSet;
+
+use crate::errors::{SocialContextError, SocialContextResult};
+use crate::link_adapter::chunked_diffs::get_reference_diff;
+use crate::link_adapter::commit::commit;
+use crate::link_adapter::history::diff_between;
+use crate::link_adapter::render::render_at;
+use crate::link_adapter::revisions::current_revision;
+use crate::retriever::PerspectiveDiffRetreiver;
+use crate::Hash;
+
+/// Commits the changes undoing the given commit on top of our current revision, keeping
+/// everything that happened after it.
+///
+/// Only the links the commit touched are changed, each back to its state at the parent of the
+/// commit. Simply inverting the diff would remove links the commit added although they already
+/// existed, and add links it removed although they never existed.
+pub fn revert<Retriever: PerspectiveDiffRetreiver>(
+    hash: Hash,
+    properties: &DnaProperties,
+) -> SocialContextResult<Hash> {
+    let current = current_revision::<Retriever>()?.ok_or(SocialContextError::NoCurrentRevision)?;
+    let reference = Retriever::get::<PerspectiveDiffEntryReference>(hash.clone())?;
+    let parent = match &reference.parents {
+        Some(parents) if parents.len() > 1 => {
+            return Err(SocialContextError::CannotRevertMerge(hash))
+        }
+        Some(parents) => parents.first().cloned(),
+        None => None,
+    };
+    let diff = get_reference_diff::<Retriever>(&reference)?;
+    let touched = diff
+        .additions
+        .into_iter()
+        .chain(diff.removals)
+        .collect::<BTreeSet<LinkExpression>>();
+
+    let inverse = match parent {
+        Some(parent) => diff_between::<Retriever>(hash, parent)?,
+        //Nothing came before the very first commit
+        None => PerspectiveDiff {
+            additions: vec![],
+            removals: render_at::<Retriever>(hash)?.links,
+        },
+    };
+    let inverse = PerspectiveDiff {
+        additions: inverse
+            .additions
+            .into_iter()
+            .filter(|link| touched.contains(link))
+            .collect(),
+        removals: inverse
+            .removals
+            .into_iter()
+            .filter(|link| touched.contains(link))
+            .collect(),
+    };
+    if inverse.total_diff_number() == 0 {
+        return Ok(current.hash);
+    }
+    commit::<Retriever>(inverse, properties)
+}
+
+/// Commits the diff from our current revision back to the perspective at the given revision,
+/// undoing everything that happened after it. History is kept, so peers sync it like any commit.
+pub fn checkout<Retriever: PerspectiveDiffRetreiver>(
+    hash: Hash,
+    properties: &DnaProperties,
+) -> SocialContextResult<Hash> {
+    let current = current_revision::<Retriever>()?.ok_or(SocialContextError::NoCurrentRevision)?;
+    let diff = diff_between::<Retriever>(current.hash.clone(), hash)?;
+    if diff.total_diff_number() == 0 {
+        return Ok(current.hash);
+    }
+    commit::<Retriever>(diff, properties)
+}
+
+#[cfg(test)]
+mod tests {
+    use perspective_diff_sync_integrity::PerspectiveDiff;
+
+    use super::{checkout, revert};
+    use crate::link_adapter::commit::commit;
+    use crate::link_adapter::render::render;
+    use crate::link_adapter::tests::{reset_mocked_graph, test_properties};
+    use crate::retriever::{MockPerspectiveGraph, CURRENT_REVISION};
+    use crate::utils::create_link_expression;
+
+    #[test]
+    fn reverts_commits_and_checks_out_revisions() {
+        reset_mocked_graph();
+
+        let properties = test_properties();
+        let a1 = create_link_expression("a", "1");
+        let a2 = create_link_expression("a", "2");
+        let a3 = create_link_expression("a", "3");
+
+        let first = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![a1.clone()],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+        let import = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![a2.clone(), a3.clone()],
+                removals: vec![a1.clone()],
+            },
+            &properties,
+        )
+        .unwrap();
+
+        let reverted = revert::<MockPerspectiveGraph>(import, &properties).unwrap();
+        assert_eq!(
+            render::<MockPerspectiveGraph>().unwrap().links,
+            vec![a1.clone()]
+        );
+
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![a3.clone()],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+        assert_eq!(
+            render::<MockPerspectiveGraph>().unwrap().links,
+            vec![a1.clone(), a3]
+        );
+
+        checkout::<MockPerspectiveGraph>(first, &properties).unwrap();
+        assert_eq!(
+            render::<MockPerspectiveGraph>().unwrap().links,
+            vec![a1.clone()]
+        );
+        //Nothing changed since, so there is nothing to commit
+        let current = CURRENT_REVISION.lock().unwrap().clone().unwrap();
+        assert_eq!(
+            checkout::<MockPerspectiveGraph>(reverted, &properties).unwrap(),
+            current
+        );
+    }
+    #[test]
+    fn reverts_only_what_a_commit_changed() {
+        reset_mocked_graph();
+
+        let properties = test_properties();
+        let a1 = create_link_expression("a", "1");
+        let a2 = create_link_expression("a", "2");
+        let never_added = create_link_expression("a", "never added");
+
+        commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![a1.clone()],
+                removals: vec![],
+            },
+            &properties,
+        )
+        .unwrap();
+        //Adds a1 once more and removes a link that never existed
+        let redundant = commit::<MockPerspectiveGraph>(
+            PerspectiveDiff {
+                additions: vec![a1.clone(), a2.clone()],
+                removals: vec![never_added],
+            },
+            &properties,
+        )
+        .unwrap();
+
+        revert::<MockPerspectiveGraph>(redundant, &properties).unwrap();
+        assert_eq!(render::<MockPerspectiveGraph>().unwrap().links, vec![a1]);
+    }
+}
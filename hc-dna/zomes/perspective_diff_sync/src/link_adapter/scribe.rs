@@ -0,0 +1,133 @@
+use chrono::{DateTime, Utc};
+use perspective_diff_sync_integrity::properties::DnaProperties;
+use perspective_diff_sync_integrity::LocalPeerSeen;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::errors::SocialContextResult;
+use crate::retriever::PerspectiveDiffRetreiver;
+
+/// Records that we received a broadcast from the given peer.
+/// Sightings are written to our source chain, so a peer is only recorded again once half the
+/// liveness timeout passed since its last recorded sighting.
+pub fn peer_seen<Retriever: PerspectiveDiffRetreiver>(
+    did: String,
+    now: DateTime<Utc>,
+    properties: &DnaProperties,
+) -> SocialContextResult<()> {
+    let recently_seen =
+        Retriever::peers_seen_since(now - properties.scribe_liveness_timeout() / 2)?
+            .iter()
+            .any(|peer| peer.did == did);
+    if recently_seen {
+        return Ok(());
+    }
+    Retriever::record_peer_seen(LocalPeerSeen {
+        did,
+        timestamp: now,
+    })
+}
+
+/// The latest of the given sightings of every peer, leaving out peers not seen since `since`
+pub fn latest_sightings<I>(sightings: I, since: DateTime<Utc>) -> Vec<LocalPeerSeen>
+where
+    I: IntoIterator<Item = LocalPeerSeen>,
+{
+    let mut latest = BTreeMap::<String, DateTime<Utc>>::new();
+    for peer in sightings {
+        if peer.timestamp >= since {
+            let seen = latest.entry(peer.did).or_insert(peer.timestamp);
+            if *seen < peer.timestamp {
+                *seen = peer.timestamp;
+            }
+        }
+    }
+    latest
+        .into_iter()
+        .map(|(did, timestamp)| LocalPeerSeen { did, timestamp })
+        .collect()
+}
+
+/// All peers we received a broadcast from within the liveness timeout
+pub fn live_peers<Retriever: PerspectiveDiffRetreiver>(
+    now: DateTime<Utc>,
+    properties: &DnaProperties,
+) -> SocialContextResult<BTreeSet<String>> {
+    Ok(
+        Retriever::peers_seen_since(now - properties.scribe_liveness_timeout())?
+            .into_iter()
+            .map(|peer| peer.did)
+            .collect(),
+    )
+}
+
+/// The scribe is the lexically first DID out of ourselves and all live peers
+pub fn elect_scribe(me: &str, peers: &BTreeSet<String>) -> String {
+    match peers.iter().next() {
+        Some(first) if first.as_str() < me => first.clone(),
+        _ => me.to_string(),
+    }
+}
+
+pub fn am_i_scribe<Retriever: PerspectiveDiffRetreiver>(
+    me: &str,
+    now: DateTime<Utc>,
+    properties: &DnaProperties,
+) -> SocialContextResult<bool> {
+    let peers = live_peers::<Retriever>(now, properties)?;
+    Ok(elect_scribe(me, &peers) == me)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use perspective_diff_sync_integrity::properties::DnaProperties;
+
+    use super::{am_i_scribe, peer_seen};
+    use crate::retriever::{MockPerspectiveGraph, PerspectiveDiffRetreiver, PEERS_SEEN};
+
+    #[test]
+    fn elects_first_live_peer_as_scribe() {
+        PEERS_SEEN.lock().unwrap().clear();
+        let properties = DnaProperties::default();
+        let now = Utc::now();
+
+        //Without any peers we are our own scribe
+        assert!(am_i_scribe::<MockPerspectiveGraph>("did:test:bob", now, &properties).unwrap());
+
+        peer_seen::<MockPerspectiveGraph>(String::from("did:test:alice"), now, &properties)
+            .unwrap();
+        peer_seen::<MockPerspectiveGraph>(String::from("did:test:carol"), now, &properties)
+            .unwrap();
+        assert!(!am_i_scribe::<MockPerspectiveGraph>("did:test:bob", now, &properties).unwrap());
+        assert!(am_i_scribe::<MockPerspectiveGraph>("did:test:aaron", now, &properties).unwrap());
+
+        //Once alice stops broadcasting, bob takes over
+        let later = now + Duration::seconds(properties.scribe_liveness_timeout_s + 1);
+        peer_seen::<MockPerspectiveGraph>(String::from("did:test:carol"), later, &properties)
+            .unwrap();
+        assert!(am_i_scribe::<MockPerspectiveGraph>("did:test:bob", later, &properties).unwrap());
+    }
+
+    #[test]
+    fn records_sightings_once_per_half_timeout() {
+        PEERS_SEEN.lock().unwrap().clear();
+        let properties = DnaProperties {
+            scribe_liveness_timeout_s: 10,
+            ..DnaProperties::default()
+        };
+        let now = Utc::now();
+
+        for seconds in 0..properties.scribe_liveness_timeout_s {
+            peer_seen::<MockPerspectiveGraph>(
+                String::from("did:test:alice"),
+                now + Duration::seconds(seconds),
+                &properties,
+            )
+            .unwrap();
+        }
+        assert_eq!(PEERS_SEEN.lock().unwrap().len(), 2);
+        let peers = MockPerspectiveGraph::peers_seen_since(now).unwrap();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].timestamp, now + Duration::seconds(6));
+    }
+}
@@ -1,261 +1,176 @@
 use hdk::prelude::*;
+use perspective_diff_sync_integrity::properties::DnaProperties;
 use perspective_diff_sync_integrity::{
-    LinkExpression, LinkTypes, PerspectiveDiff, PerspectiveDiffEntryReference, Snapshot,
+    EntryTypes, LinkExpression, LinkTypes, LocalPendingSnapshot, LocalPerspectiveCache,
+    PerspectiveDiffEntryReference, Snapshot, SnapshotStatus,
 };
+use std::collections::BTreeSet;
 
-use crate::errors::{SocialContextError, SocialContextResult};
+use crate::errors::SocialContextResult;
 use crate::link_adapter::chunked_diffs::ChunkedDiffs;
-use crate::retriever::HolochainRetreiver;
+use crate::link_adapter::query::build_index;
+use crate::link_adapter::render::collect_links;
+use crate::retriever::PerspectiveDiffRetreiver;
 use crate::utils::get_now;
-use crate::{Hash, CHUNK_SIZE};
+use crate::Hash;
 
-struct SearchPosition {
-    hash: Hash,
-    is_unseen: bool,
-}
-
-pub fn generate_snapshot(
-    latest: HoloHash<holo_hash::hash_type::Action>,
+/// Creates the snapshot of the perspective at latest, given the links collected at latest.
+///
+/// Only the net state is stored, i.e. the links that exist at latest after applying all diffs in
+/// causal order, so a snapshot grows with the live perspective instead of with its history.
+/// Since latest descends from every diff the snapshot contains, it is the only hash stored in
+/// `included_diffs`: the frontier of the included history.
+/// Links of banned authors are kept, since bans can be lifted; rendering retracts them.
+pub fn generate_snapshot<Retriever: PerspectiveDiffRetreiver>(
+    latest: Hash,
+    links: BTreeSet<LinkExpression>,
+    properties: &DnaProperties,
 ) -> SocialContextResult<Snapshot> {
     debug!("===PerspectiveDiffSync.generate_snapshot(): Function start");
     let fn_start = get_now()?.time();
-    let mut search_position = SearchPosition {
-        hash: latest.clone(),
-        is_unseen: false,
-    };
-    let mut seen: HashSet<Hash> = HashSet::new();
-    let mut unseen_parents = vec![];
-
-    let mut all_additions = BTreeSet::new();
-    let mut all_removals = BTreeSet::new();
-
-    loop {
-        let diff = get(search_position.hash.clone(), GetOptions::latest())?
-            .ok_or(SocialContextError::InternalError(
-                "generate_snapshot(): Could not find entry while populating search",
-            ))?
-            .entry()
-            .to_app_option::<PerspectiveDiffEntryReference>()?
-            .ok_or(SocialContextError::InternalError(
-                "Expected element to contain app entry data",
-            ))?;
-        if diff.diffs_since_snapshot == 0 && search_position.hash != latest {
-            let now = get_now()?.time();
-            let mut snapshot_links = get_links(
-                hash_entry(&diff)?,
-                LinkTypes::Snapshot,
-                Some(LinkTag::new("snapshot")),
-            )?;
-            let after = get_now()?.time();
-            debug!("===PerspectiveDiffSync.generate_snapshot() - Profiling: Took {} to get the snapshot links", (after - now).num_milliseconds());
-            if snapshot_links.len() == 0 {
-                debug!("===PerspectiveDiffSync.generate_snapshot() - ERROR: Did not find snapshot link where we expected to!");
-                let should_break = handle_parents(
-                    diff,
-                    &mut search_position,
-                    &mut seen,
-                    &mut unseen_parents,
-                    &mut all_additions,
-                    &mut all_removals,
-                )?;
-                if should_break {
-                    break;
-                }
-            } else {
-                let now = get_now()?.time();
-                //get snapshot and add elements to out
-                let snapshot = get(
-                    snapshot_links
-                        .remove(0)
-                        .target
-                        .into_entry_hash()
-                        .expect("Could not get entry_hash"),
-                    GetOptions::latest(),
-                )?
-                .ok_or(SocialContextError::InternalError(
-                    "Could not find diff entry for given diff entry reference",
-                ))?
-                .entry()
-                .to_app_option::<Snapshot>()?
-                .ok_or(SocialContextError::InternalError(
-                    "Expected element to contain app entry data",
-                ))?;
-                let after = get_now()?.time();
-                debug!("===PerspectiveDiffSync.generate_snapshot() - Profiling: Took {} to get the snapshot entry", (after - now).num_milliseconds());
-
-                let diff = ChunkedDiffs::from_entries::<HolochainRetreiver>(snapshot.diff_chunks)?
-                    .into_aggregated_diff();
-                for addition in diff.additions.iter() {
-                    all_additions.insert(addition.clone());
-                }
-                for removal in diff.removals.iter() {
-                    all_removals.insert(removal.clone());
-                }
-                for hash in snapshot.included_diffs.iter() {
-                    seen.insert(hash.clone());
-                }
-                //Be careful with break here where there are still unseen parents
-                if unseen_parents.len() == 0 {
-                    // debug!("No more unseen parents within snapshot block");
-                    break;
-                } else {
-                    search_position = unseen_parents.remove(0);
-                }
-            };
-        } else {
-            let should_break = handle_parents(
-                diff,
-                &mut search_position,
-                &mut seen,
-                &mut unseen_parents,
-                &mut all_additions,
-                &mut all_removals,
-            )?;
-            if should_break {
-                break;
-            }
-        }
-    }
-
-    let mut chunked_diffs = ChunkedDiffs::new(*CHUNK_SIZE);
 
-    chunked_diffs.add_additions(all_additions.into_iter().collect());
-    chunked_diffs.add_removals(all_removals.into_iter().collect());
+    let mut chunked_diffs = ChunkedDiffs::new(properties.chunk_size);
+    chunked_diffs.add_additions(links.into_iter().collect());
 
     let snapshot = Snapshot {
-        diff_chunks: chunked_diffs.into_entries::<HolochainRetreiver>()?,
-        included_diffs: seen.into_iter().collect(),
+        diff_chunks: chunked_diffs.into_entries::<Retriever>(properties)?,
+        included_diffs: vec![latest],
     };
 
     let fn_end = get_now()?.time();
-    debug!("===PerspectiveDiffSync.generate_snapshot() - Profiling: Took: {} to complete generate_snapshot function", (fn_end - fn_start).num_milliseconds());
+    debug!(
+        "===PerspectiveDiffSync.generate_snapshot() - Profiling: Took: {} to complete generate_snapshot function",
+        (fn_end - fn_start).num_milliseconds()
+    );
     Ok(snapshot)
 }
 
-fn handle_parents(
-    diff: PerspectiveDiffEntryReference,
-    search_position: &mut SearchPosition,
-    seen: &mut HashSet<Hash>,
-    unseen_parents: &mut Vec<SearchPosition>,
-    all_additions: &mut BTreeSet<LinkExpression>,
-    all_removals: &mut BTreeSet<LinkExpression>,
-) -> SocialContextResult<bool> {
-    //Check if entry is already in graph
-    if !seen.contains(&search_position.hash) {
-        seen.insert(search_position.hash.clone());
-        let diff_entry = get(diff.diff.clone(), GetOptions::latest())?
-            .ok_or(SocialContextError::InternalError(
-                "Could not find diff entry for given diff entry reference",
-            ))?
-            .entry()
-            .to_app_option::<PerspectiveDiff>()?
-            .ok_or(SocialContextError::InternalError(
-                "Expected element to contain app entry data",
-            ))?;
-
-        for addition in diff_entry.additions.iter() {
-            all_additions.insert(addition.clone());
-        }
-        for removal in diff_entry.removals.iter() {
-            all_removals.insert(removal.clone());
+/// Creates and links the snapshot of every revision commit left pending, oldest first so each
+/// snapshot can build on top of the previous one. Returns the revisions that got a snapshot.
+pub fn create_pending_snapshots<Retriever: PerspectiveDiffRetreiver>(
+    properties: &DnaProperties,
+) -> SocialContextResult<Vec<Hash>> {
+    let mut created = vec![];
+    for revision in Retriever::pending_snapshots()? {
+        if snapshot_status::<Retriever>(revision.clone())? == SnapshotStatus::Pending {
+            create_snapshot::<Retriever>(revision.clone(), properties)?;
+            created.push(revision.clone());
         }
+        Retriever::update_pending_snapshot(LocalPendingSnapshot {
+            revision,
+            pending: false,
+        })?;
+    }
+    Ok(created)
+}
 
-        if diff.parents.is_none() {
-            //No parents, we have reached the end of the chain
-            //Now move onto traversing unseen parents, or break if we dont have any other paths to search
-            if unseen_parents.len() == 0 {
-                // debug!("No more unseen items within parent block");
-                Ok(true)
-            } else {
-                // debug!("Moving onto unseen fork items within parent block");
-                *search_position = unseen_parents.remove(0);
-                Ok(false)
-            }
-        } else {
-            //Do the fork traversals
-            let mut parents = diff.parents.unwrap();
-            //Check if all parents have already been seen, if so then break or move onto next unseen parents
-            //TODO; we should use a seen set here versus array iter
-            if parents.iter().all(|val| seen.contains(val)) {
-                if unseen_parents.len() == 0 {
-                    // debug!("Parents of item seen and unseen 0");
-                    return Ok(true);
-                } else {
-                    // debug!("last moving onto unseen");
-                    *search_position = unseen_parents.remove(0);
-                    Ok(false)
-                }
-            } else {
-                *search_position = SearchPosition {
-                    hash: parents.remove(0),
-                    is_unseen: false,
-                };
-                // debug!("Appending parents to look up");
-                unseen_parents.append(
-                    &mut parents
-                        .into_iter()
-                        .map(|val| SearchPosition {
-                            hash: val,
-                            is_unseen: true,
-                        })
-                        .collect(),
-                );
-                Ok(false)
-            }
-        }
-    } else if search_position.is_unseen {
-        //The parent for this branch is already seen so likely already explored and we are part of the main branch
-        if unseen_parents.len() == 0 {
-            // debug!("No more unseen items within parent block");
-            Ok(true)
-        } else {
-            // debug!("Moving onto unseen fork items within parent block");
-            *search_position = unseen_parents.remove(0);
-            Ok(false)
-        }
+/// Creates the snapshot of revision and makes it the render cache, so the private copy of the
+/// perspective is only written once per snapshot interval instead of on reads
+fn create_snapshot<Retriever: PerspectiveDiffRetreiver>(
+    revision: Hash,
+    properties: &DnaProperties,
+) -> SocialContextResult<()> {
+    let reference = Retriever::get::<PerspectiveDiffEntryReference>(revision.clone())?;
+    //Snapshot and cache keep the links of banned authors, so lifting a ban needs no full render
+    let links = collect_links::<Retriever>(revision.clone())?;
+    let snapshot = generate_snapshot::<Retriever>(revision.clone(), links.clone(), properties)?;
+
+    let now = get_now()?.time();
+    Retriever::create_entry(EntryTypes::Snapshot(snapshot.clone()))?;
+    Retriever::create_link(
+        Retriever::hash_entry(reference)?.into(),
+        Retriever::hash_entry(snapshot)?.into(),
+        LinkTypes::Snapshot,
+        LinkTag::new("snapshot"),
+    )?;
+    let links = links.into_iter().collect::<Vec<LinkExpression>>();
+    Retriever::update_perspective_cache(LocalPerspectiveCache {
+        revision,
+        index: Some(build_index(&links)),
+        links,
+    })?;
+    let after = get_now()?.time();
+    debug!(
+        "===PerspectiveDiffSync.create_snapshot() - Profiling: Took {} to create snapshot entry and link",
+        (after - now).num_milliseconds()
+    );
+    Ok(())
+}
+
+pub fn snapshot_status<Retriever: PerspectiveDiffRetreiver>(
+    revision: Hash,
+) -> SocialContextResult<SnapshotStatus> {
+    let reference = Retriever::get::<PerspectiveDiffEntryReference>(revision)?;
+    if reference.diffs_since_snapshot != 0 {
+        return Ok(SnapshotStatus::NotScheduled);
+    }
+    let snapshot_links = Retriever::get_link_targets(
+        Retriever::hash_entry(reference)?.into(),
+        LinkTypes::Snapshot,
+        Some(LinkTag::new("snapshot")),
+    )?;
+    if snapshot_links.is_empty() {
+        Ok(SnapshotStatus::Pending)
     } else {
-        if diff.parents.is_none() {
-            //No parents, we have reached the end of the chain
-            //Now move onto traversing unseen parents, or break if we dont have any other paths to search
-            if unseen_parents.len() == 0 {
-                // debug!("No more unseen items within parent block");
-                Ok(true)
-            } else {
-                // debug!("Moving onto unseen fork items within parent block");
-                *search_position = unseen_parents.remove(0);
-                Ok(false)
-            }
-        } else {
-            //Do the fork traversals
-            let mut parents = diff.parents.unwrap();
-            //Check if all parents have already been seen, if so then break or move onto next unseen parents
-            //TODO; we should use a seen set here versus array iter
-            if parents.iter().all(|val| seen.contains(val)) {
-                if unseen_parents.len() == 0 {
-                    // debug!("Parents of item seen and unseen 0");
-                    Ok(true)
-                } else {
-                    // debug!("last moving onto unseen");
-                    *search_position = unseen_parents.remove(0);
-                    Ok(false)
-                }
-            } else {
-                *search_position = SearchPosition {
-                    hash: parents.remove(0),
-                    is_unseen: false,
-                };
-                // debug!("Appending parents to look up");
-                unseen_parents.append(
-                    &mut parents
-                        .into_iter()
-                        .map(|val| SearchPosition {
-                            hash: val,
-                            is_unseen: true,
-                        })
-                        .collect(),
-                );
-                Ok(false)
-            }
+        Ok(SnapshotStatus::Created)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use perspective_diff_sync_integrity::properties::DnaProperties;
+    use perspective_diff_sync_integrity::PerspectiveDiff;
+
+    use super::generate_snapshot;
+    use crate::link_adapter::chunked_diffs::ChunkedDiffs;
+    use crate::link_adapter::commit::commit;
+    use crate::link_adapter::render::collect_links;
+    use crate::link_adapter::tests::{reset_mocked_graph, test_properties};
+    use crate::retriever::MockPerspectiveGraph;
+    use crate::utils::create_link_expression;
+
+    #[test]
+    fn snapshots_store_only_the_net_perspective() {
+        reset_mocked_graph();
+
+        let properties = DnaProperties {
+            chunk_size: 2,
+            ..test_properties()
+        };
+        let a1 = create_link_expression("a", "1");
+        let a2 = create_link_expression("a", "2");
+        let a3 = create_link_expression("a", "3");
+        let diffs = vec![
+            (vec![a1.clone(), a2.clone(), a3.clone()], vec![]),
+            (vec![], vec![a1.clone(), a2.clone()]),
+            //Added again after its removal, so it has to be part of the snapshot
+            (vec![a1.clone()], vec![]),
+        ];
+        let mut latest = None;
+        for (additions, removals) in diffs {
+            latest = Some(
+                commit::<MockPerspectiveGraph>(
+                    PerspectiveDiff {
+                        additions,
+                        removals,
+                    },
+                    &properties,
+                )
+                .unwrap(),
+            );
         }
+        let latest = latest.unwrap();
+
+        let links = collect_links::<MockPerspectiveGraph>(latest.clone()).unwrap();
+        let snapshot =
+            generate_snapshot::<MockPerspectiveGraph>(latest.clone(), links, &properties).unwrap();
+        assert_eq!(snapshot.included_diffs, vec![latest]);
+        assert_eq!(snapshot.diff_chunks.len(), 1);
+
+        let diff = ChunkedDiffs::from_entries::<MockPerspectiveGraph>(snapshot.diff_chunks, 2)
+            .unwrap()
+            .into_aggregated_diff();
+        assert_eq!(diff.additions, vec![a1, a3]);
+        assert!(diff.removals.is_empty());
     }
 }
@@ -101,3 +101,236 @@ pub fn test_merge_fast_forward_none_source() {
     assert!(res.is_ok());
     assert_eq!(res.unwrap(), ActionHash::from_raw_36(vec![0xdb; 36]));
 }
+
+/// Empties the mocked DHT and resets the local state of the mocked agent
+#[cfg(test)]
+pub(crate) fn reset_mocked_graph() {
+    use crate::retriever::{
+        default_agent, MockPerspectiveGraph, AUTHORS, COMMITS, CREATED, CURRENT_REVISION,
+        GLOBAL_MOCKED_GRAPH, MOCK_AGENT, PENDING_SNAPSHOTS, PERSPECTIVE_CACHE,
+    };
+
+    *GLOBAL_MOCKED_GRAPH.lock().unwrap() =
+        MockPerspectiveGraph::from_dot("digraph{}").expect("can create mock graph from empty dot");
+    *CURRENT_REVISION.lock().unwrap() = None;
+    *PERSPECTIVE_CACHE.lock().unwrap() = None;
+    PENDING_SNAPSHOTS.lock().unwrap().clear();
+    COMMITS.lock().unwrap().clear();
+    *MOCK_AGENT.lock().unwrap() = default_agent();
+    AUTHORS.lock().unwrap().clear();
+    CREATED.lock().unwrap().clear();
+}
+
+/// Properties tests commit with: no signals, no spam limit and the made up DIDs of
+/// `create_link_expression` accepted, everything else at its default
+#[cfg(test)]
+pub(crate) fn test_properties() -> perspective_diff_sync_integrity::properties::DnaProperties {
+    use perspective_diff_sync_integrity::properties::DnaProperties;
+
+    DnaProperties {
+        enable_signals: false,
+        enforce_spam_limit: 0,
+        accept_unresolvable_dids: true,
+        ..DnaProperties::default()
+    }
+}
+
+#[cfg(test)]
+fn commit_all(diffs: Vec<perspective_diff_sync_integrity::PerspectiveDiff>) {
+    use crate::link_adapter::commit::commit;
+    use crate::retriever::MockPerspectiveGraph;
+    use perspective_diff_sync_integrity::properties::DnaProperties;
+
+    reset_mocked_graph();
+
+    //No snapshots and so no render cache either, render has to replay every single diff
+    let properties = DnaProperties {
+        snapshot_interval: usize::MAX,
+        ..test_properties()
+    };
+    for diff in diffs {
+        commit::<MockPerspectiveGraph>(diff, &properties).expect("commit does not error");
+    }
+}
+
+#[test]
+pub fn test_render_applies_removals_after_additions() {
+    use perspective_diff_sync_integrity::PerspectiveDiff;
+
+    use crate::link_adapter::render::render;
+    use crate::retriever::MockPerspectiveGraph;
+    use crate::utils::create_link_expression;
+
+    let kept = create_link_expression("a", "kept");
+    let mut diffs = vec![PerspectiveDiff {
+        additions: vec![kept.clone()],
+        removals: vec![],
+    }];
+    //Enough links that a hash ordered replay would put some removals before their additions
+    for i in 0..10 {
+        let link = create_link_expression("a", &format!("{}", i));
+        diffs.push(PerspectiveDiff {
+            additions: vec![link.clone()],
+            removals: vec![],
+        });
+        diffs.push(PerspectiveDiff {
+            additions: vec![],
+            removals: vec![link],
+        });
+    }
+    commit_all(diffs);
+
+    let perspective = render::<MockPerspectiveGraph>().expect("render does not error");
+    assert_eq!(perspective.links, vec![kept]);
+}
+
+#[test]
+pub fn test_render_keeps_links_added_again_after_removal() {
+    use perspective_diff_sync_integrity::PerspectiveDiff;
+
+    use crate::link_adapter::render::render;
+    use crate::retriever::MockPerspectiveGraph;
+    use crate::utils::create_link_expression;
+
+    let link = create_link_expression("a", "1");
+    let other = create_link_expression("a", "2");
+    commit_all(vec![
+        PerspectiveDiff {
+            additions: vec![link.clone()],
+            removals: vec![],
+        },
+        PerspectiveDiff {
+            additions: vec![other.clone()],
+            removals: vec![link.clone()],
+        },
+        PerspectiveDiff {
+            additions: vec![link.clone()],
+            removals: vec![other],
+        },
+    ]);
+
+    let perspective = render::<MockPerspectiveGraph>().expect("render does not error");
+    assert_eq!(perspective.links, vec![link]);
+}
+
+#[test]
+pub fn test_squashed_diff_applies_diffs_in_causal_order() {
+    use perspective_diff_sync_integrity::PerspectiveDiff;
+
+    use crate::link_adapter::revisions::current_revision;
+    use crate::link_adapter::workspace::Workspace;
+    use crate::retriever::MockPerspectiveGraph;
+    use crate::utils::create_link_expression;
+
+    let added = create_link_expression("a", "added");
+    let removed = create_link_expression("a", "removed");
+    let mut diffs = vec![];
+    for _ in 0..3 {
+        diffs.push(PerspectiveDiff {
+            additions: vec![removed.clone()],
+            removals: vec![],
+        });
+        diffs.push(PerspectiveDiff {
+            additions: vec![],
+            removals: vec![removed.clone()],
+        });
+    }
+    diffs.push(PerspectiveDiff {
+        additions: vec![added.clone()],
+        removals: vec![],
+    });
+    commit_all(diffs);
+
+    let current = current_revision::<MockPerspectiveGraph>()
+        .expect("current revision does not error")
+        .expect("there is a current revision");
+    let mut workspace = Workspace::new();
+    workspace
+        .collect_only_from_latest::<MockPerspectiveGraph>(current.hash)
+        .expect("collect does not error");
+    let diff = workspace
+        .squashed_diff::<MockPerspectiveGraph>()
+        .expect("squashed diff does not error");
+
+    assert_eq!(diff.additions, vec![added]);
+    assert_eq!(diff.removals, vec![removed]);
+}
+
+#[test]
+pub fn test_render_skips_diffs_included_in_snapshot_of_other_branch() {
+    use perspective_diff_sync_integrity::properties::DnaProperties;
+    use perspective_diff_sync_integrity::{
+        EntryTypes, PerspectiveDiff, PerspectiveDiffEntryReference,
+    };
+
+    use crate::link_adapter::commit::commit;
+    use crate::link_adapter::render::render_at;
+    use crate::link_adapter::snapshots::create_pending_snapshots;
+    use crate::retriever::{MockPerspectiveGraph, PerspectiveDiffRetreiver};
+    use crate::utils::create_link_expression;
+
+    reset_mocked_graph();
+    let properties = DnaProperties {
+        snapshot_interval: 3,
+        ..test_properties()
+    };
+    let removed = create_link_expression("a", "removed");
+    let kept = create_link_expression("a", "kept");
+    let added = create_link_expression("a", "added");
+    let forked = create_link_expression("a", "forked");
+
+    let fork_point = commit::<MockPerspectiveGraph>(
+        PerspectiveDiff {
+            additions: vec![removed.clone()],
+            removals: vec![],
+        },
+        &properties,
+    )
+    .unwrap();
+    commit::<MockPerspectiveGraph>(
+        PerspectiveDiff {
+            additions: vec![kept.clone()],
+            removals: vec![],
+        },
+        &properties,
+    )
+    .unwrap();
+    let snapshotted = commit::<MockPerspectiveGraph>(
+        PerspectiveDiff {
+            additions: vec![added.clone()],
+            removals: vec![removed],
+        },
+        &properties,
+    )
+    .unwrap();
+    create_pending_snapshots::<MockPerspectiveGraph>(&properties).unwrap();
+
+    //A branch forked off before the snapshot reaches the diffs it only holds the result of
+    MockPerspectiveGraph::update_current_revision(fork_point, chrono::Utc::now()).unwrap();
+    let fork = commit::<MockPerspectiveGraph>(
+        PerspectiveDiff {
+            additions: vec![forked.clone()],
+            removals: vec![],
+        },
+        &properties,
+    )
+    .unwrap();
+    let merge_diff =
+        MockPerspectiveGraph::create_entry(EntryTypes::PerspectiveDiff(PerspectiveDiff::new()))
+            .unwrap();
+    let merge = MockPerspectiveGraph::create_entry(EntryTypes::PerspectiveDiffEntryReference(
+        PerspectiveDiffEntryReference {
+            diff: merge_diff,
+            parents: Some(vec![snapshotted, fork]),
+            diffs_since_snapshot: 1,
+            metadata: None,
+            diff_chunks: None,
+        },
+    ))
+    .unwrap();
+
+    let perspective = render_at::<MockPerspectiveGraph>(merge).expect("render does not error");
+    let mut expected = vec![kept, added, forked];
+    expected.sort();
+    assert_eq!(perspective.links, expected);
+}
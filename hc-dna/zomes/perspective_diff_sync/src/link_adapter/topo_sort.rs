@@ -1,9 +1,14 @@
 use crate::errors::{SocialContextError, SocialContextResult};
 use hdk::prelude::*;
 use perspective_diff_sync_integrity::PerspectiveDiffEntryReference;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 // Applies Kahn's algorithm for topologically sorting a graph
+//
+// Concurrent branches are ordered deterministically: nodes whose parents are all sorted are kept
+// on a stack, so we always continue with the children released by the node added last, taking
+// those in descending hash order. Orphans are taken in reverse input order, which is why callers
+// that need an order independent of how they collected the entries pass them sorted by hash.
 pub fn topo_sort_diff_references(
     arr: &Vec<(
         HoloHash<holo_hash::hash_type::Action>,
@@ -16,7 +21,7 @@ pub fn topo_sort_diff_references(
     )>,
 > {
     type Hash = HoloHash<holo_hash::hash_type::Action>;
-    let mut result = Vec::<(Hash, PerspectiveDiffEntryReference)>::new();
+    let mut result = Vec::<(Hash, PerspectiveDiffEntryReference)>::with_capacity(arr.len());
 
     // first collect orphaned nodes (=without parent) as starting points:
     let mut orphaned_nodes: Vec<(Hash, PerspectiveDiffEntryReference)> = arr
@@ -27,87 +32,115 @@ pub fn topo_sort_diff_references(
 
     if orphaned_nodes.len() == 0 {
         debug!("No orphans found! Length: {}, list: {:?}", arr.len(), arr);
-        return Err(SocialContextError::InternalError(
-            "Can't topologically sort list without orphan!",
-        ));
     }
 
-    let mut edges = BTreeSet::new();
-    for i in 0..arr.len() {
-        if let Some(parents) = &arr[i].1.parents {
-            for p in 0..parents.len() {
-                let child = arr[i].0.clone();
-                let parent = parents[p].clone();
-                edges.insert((child, parent));
+    // Remember the children of every parent and count the distinct parents of every child,
+    // so releasing the children of a sorted node doesn't need a scan over all edges
+    let mut positions = HashMap::<Hash, usize>::new();
+    let mut children = HashMap::<Hash, BTreeSet<Hash>>::new();
+    let mut in_degree = HashMap::<Hash, usize>::new();
+    for (position, (hash, reference)) in arr.iter().enumerate() {
+        positions.entry(hash.clone()).or_insert(position);
+        if let Some(parents) = &reference.parents {
+            for parent in parents {
+                if children
+                    .entry(parent.clone())
+                    .or_default()
+                    .insert(hash.clone())
+                {
+                    *in_degree.entry(hash.clone()).or_insert(0) += 1;
+                }
             }
         }
     }
 
     // Starting from the nodes without parents...
     while let Some(n) = orphaned_nodes.pop() {
-        //.. we put them into the result list.
-        result.push(n.clone());
-
-        println!("Added orphan {:?}", n);
-
-        // and then we look for any nodes that have it as parent
-        // (using the edges set)
-        let edges_with_n_as_parent = edges
-            .iter()
-            .filter(|&e| e.1 == n.0)
-            .cloned()
-            .collect::<Vec<(Hash, Hash)>>();
-
-        println!("Edges with orphan as parent {:?}", edges_with_n_as_parent);
-
-        // So for every parent relationship with n as parent...
-        for edge in &edges_with_n_as_parent {
-            println!("Removing edge {:?}", edge);
-            // we remove that edge
-            edges.remove(edge);
-
-            // and then check if that child of n has any other parents...
-            let child = edge.0.clone();
-
-            println!("Found child {:?}", child);
-            let edges_with_child_as_child = edges
-                .iter()
-                .filter(|&e| e.0 == child)
-                .cloned()
-                .collect::<Vec<(Hash, Hash)>>();
-
-            println!("Edges with child as child {:?}", edges_with_child_as_child);
-
-            // if the child does not have any other parents (left unprocessed)
-            if edges_with_child_as_child.len() == 0 {
-                // we're good to add the child to the results as well.
-                let child_item = arr.iter().find(|&e| e.0 == child).ok_or(SocialContextError::InternalError("Topological sort couldn't find child in input vector, which was mentioned in an edge. This can only be an error in the topological sorting code.."))?;
-                println!("Adding newly orphaned child {:?}", child_item);
-                orphaned_nodes.push((child.clone(), child_item.1.clone()));
+        // we take the children that have it as parent..
+        let released = children.remove(&n.0).unwrap_or_default();
+        //.. and put it into the result list.
+        result.push(n);
+
+        // Every child without other unsorted parents can be sorted next. They are pushed in
+        // ascending order, so the highest hash is taken first.
+        for child in released {
+            if let Some(degree) = in_degree.get_mut(&child) {
+                *degree -= 1;
+                if *degree == 0 {
+                    let child_item = &arr[positions[&child]];
+                    orphaned_nodes.push((child, child_item.1.clone()));
+                }
             }
         }
     }
 
-    if edges.len() > 0 {
-        debug!(
-            "Unresolved parent links after topologically sorting: {:?}",
-            edges
-        );
-
-        debug!("Number of unresolved parent links {:?}", edges.len());
+    let unresolved = in_degree.values().sum::<usize>();
+    if unresolved > 0 {
+        debug!("Number of unresolved parent links {:?}", unresolved);
         debug!("Number of items to sort: {:?}", arr.len());
-        Err(SocialContextError::InternalError(
-            "Cycle or missing nodes detected. Unresolved parent links after topologically sorting.",
-        ))
-        //Ok(result)
+        // Children of parents that are not part of the list can't be sorted either. Those parents
+        // most likely were not gossiped to us yet, so unlike a cycle this can resolve itself.
+        let parents_missing = arr.iter().any(|(_, reference)| {
+            reference
+                .parents
+                .iter()
+                .flatten()
+                .any(|parent| !positions.contains_key(parent))
+        });
+        if parents_missing {
+            Err(SocialContextError::MissingRevisions)
+        } else {
+            Err(SocialContextError::DagCycle)
+        }
     } else {
         Ok(result)
     }
 }
 
+/// Topologically sorts a part of the DAG, like the entries collected by a Workspace.
+/// Parents which are not part of the given entries are ignored so the oldest entries become orphans.
+pub fn topo_sort_subgraph(
+    entries: &BTreeMap<HoloHash<holo_hash::hash_type::Action>, PerspectiveDiffEntryReference>,
+) -> SocialContextResult<
+    Vec<(
+        HoloHash<holo_hash::hash_type::Action>,
+        PerspectiveDiffEntryReference,
+    )>,
+> {
+    let entries = entries
+        .iter()
+        .map(|(hash, reference)| {
+            let parents = reference.parents.as_ref().and_then(|parents| {
+                let parents = parents
+                    .iter()
+                    .filter(|parent| entries.contains_key(parent))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if parents.is_empty() {
+                    None
+                } else {
+                    Some(parents)
+                }
+            });
+            (
+                hash.clone(),
+                PerspectiveDiffEntryReference {
+                    parents,
+                    ..reference.clone()
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+    if entries.is_empty() {
+        return Ok(entries);
+    }
+    topo_sort_diff_references(&entries)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::topo_sort_diff_references;
+    use super::{topo_sort_diff_references, topo_sort_subgraph};
+    use crate::errors::SocialContextError;
     use hdk::prelude::*;
     use perspective_diff_sync_integrity::PerspectiveDiffEntryReference;
 
@@ -140,4 +173,53 @@ mod tests {
 
         assert_eq!(result, expected);
     }
+    #[test]
+    fn test_topo_sort_subgraph_ignores_missing_parents() {
+        let h1 = HoloHash::<holo_hash::hash_type::Action>::from_raw_36(vec![1; 36]);
+        let h2 = HoloHash::<holo_hash::hash_type::Action>::from_raw_36(vec![2; 36]);
+        let h3 = HoloHash::<holo_hash::hash_type::Action>::from_raw_36(vec![3; 36]);
+        let h4 = HoloHash::<holo_hash::hash_type::Action>::from_raw_36(vec![4; 36]);
+        let missing = HoloHash::<holo_hash::hash_type::Action>::from_raw_36(vec![5; 36]);
+
+        let r1 = PerspectiveDiffEntryReference::new(h1.clone(), Some(vec![h2.clone(), h3.clone()]));
+        let r2 = PerspectiveDiffEntryReference::new(h2.clone(), Some(vec![h4.clone()]));
+        let r3 = PerspectiveDiffEntryReference::new(h3.clone(), Some(vec![missing.clone()]));
+        let r4 = PerspectiveDiffEntryReference::new(h4.clone(), Some(vec![missing]));
+
+        let entries = btreemap! {
+            h1.clone() => r1.clone(),
+            h2.clone() => r2.clone(),
+            h3.clone() => r3,
+            h4.clone() => r4,
+        };
+
+        let result = topo_sort_subgraph(&entries).expect("topo sort to not error");
+        let order = result.iter().map(|e| e.0.clone()).collect::<Vec<_>>();
+        assert_eq!(order, vec![h4.clone(), h2, h3.clone(), h1]);
+        //Missing parents are stripped, present ones are kept
+        assert_eq!(result[0].1.parents, None);
+        assert_eq!(result[2].1.parents, None);
+        assert_eq!(result[3].1, r1);
+    }
+
+    #[test]
+    fn test_topo_sort_tells_missing_revisions_from_cycles() {
+        let h1 = HoloHash::<holo_hash::hash_type::Action>::from_raw_36(vec![1; 36]);
+        let h2 = HoloHash::<holo_hash::hash_type::Action>::from_raw_36(vec![2; 36]);
+        let h3 = HoloHash::<holo_hash::hash_type::Action>::from_raw_36(vec![3; 36]);
+        let missing = HoloHash::<holo_hash::hash_type::Action>::from_raw_36(vec![5; 36]);
+
+        let root = (
+            h1.clone(),
+            PerspectiveDiffEntryReference::new(h1.clone(), None),
+        );
+        let r2 = PerspectiveDiffEntryReference::new(h2.clone(), Some(vec![h1, missing]));
+        let result = topo_sort_diff_references(&vec![root.clone(), (h2.clone(), r2)]);
+        assert!(matches!(result, Err(SocialContextError::MissingRevisions)));
+
+        let r2 = PerspectiveDiffEntryReference::new(h2.clone(), Some(vec![h3.clone()]));
+        let r3 = PerspectiveDiffEntryReference::new(h3.clone(), Some(vec![h2.clone()]));
+        let result = topo_sort_diff_references(&vec![root, (h2, r2), (h3, r3)]);
+        assert!(matches!(result, Err(SocialContextError::DagCycle)));
+    }
 }
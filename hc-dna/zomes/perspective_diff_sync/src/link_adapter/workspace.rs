@@ -12,7 +12,8 @@ use std::cell::RefCell;
 use std::collections::{BTreeMap, VecDeque};
 
 use crate::errors::{SocialContextError, SocialContextResult};
-use crate::link_adapter::topo_sort::topo_sort_diff_references;
+use crate::link_adapter::chunked_diffs::get_reference_diff;
+use crate::link_adapter::topo_sort::{topo_sort_diff_references, topo_sort_subgraph};
 use crate::retriever::{hash_to_node_id, PerspectiveDiffRetreiver};
 use crate::utils::get_now;
 use crate::Hash;
@@ -130,6 +131,9 @@ impl Workspace {
         unprocessed_branches.push_back(latest);
 
         let mut snapshot_seen = vec![];
+        //Chunks of every snapshot found, and whether a branch ended somewhere else than at them
+        let mut snapshots = BTreeMap::<Hash, Vec<Hash>>::new();
+        let mut passed_beside_snapshots = false;
 
         while !unprocessed_branches.is_empty() {
             let current_hash = unprocessed_branches[0].clone();
@@ -145,10 +149,11 @@ impl Workspace {
 
             if current_diff.diffs_since_snapshot == 0 {
                 debug!("===Workspace.collect_only_from_latest(): Found a perspective diff reference containing a snapshot!");
-                let snapshot = Self::get_snapshot(current_diff.clone())?;
+                let snapshot = Self::get_snapshot::<Retriever>(current_diff.clone())?;
 
                 if snapshot.is_none() {
                     debug!("===Workspace.collect_only_from_latest(): ERROR: Expected to find snapshot link on current_diff where diffs_since_snapshot was 0");
+                    passed_beside_snapshots |= current_diff.parents.is_none();
                     self.handle_parents(current_diff, current_hash, &mut unprocessed_branches);
                 } else {
                     let mut snapshot = snapshot.unwrap();
@@ -171,6 +176,7 @@ impl Workspace {
                         PerspectiveDiffEntryReference::new(current_diff.diff, last_diff.clone()),
                     );
 
+                    snapshots.insert(current_hash.clone(), snapshot.diff_chunks.clone());
                     snapshot_seen.append(&mut snapshot.included_diffs);
 
                     // Snapshot terminates like an orphan.
@@ -179,16 +185,68 @@ impl Workspace {
                     unprocessed_branches.pop_front();
                 };
             } else {
+                passed_beside_snapshots |= current_diff.parents.is_none();
                 self.handle_parents(current_diff, current_hash, &mut unprocessed_branches);
             }
         }
 
+        //Snapshots only hold the net additions at their frontier, so diffs before it that got
+        //reached through a fork would replay links the frontier already removed
+        if !snapshots.is_empty() && (passed_beside_snapshots || snapshots.len() > 1) {
+            self.skip_snapshot_ancestors::<Retriever>(&snapshot_seen, &snapshots)?;
+        }
+
         let fn_end = get_now()?.time();
         debug!("===Workspace.collect_only_from_latest() - Profiling: Took: {} to complete collect_only_from_latest() function", (fn_end - fn_start).num_milliseconds());
 
         Ok(())
     }
 
+    /// Removes every ancestor of the snapshot frontiers from the entry map, including the chunks
+    /// of older snapshots, and drops the parents that pointed at them
+    fn skip_snapshot_ancestors<Retriever: PerspectiveDiffRetreiver>(
+        &mut self,
+        frontiers: &[Hash],
+        snapshots: &BTreeMap<Hash, Vec<Hash>>,
+    ) -> SocialContextResult<()> {
+        let mut ancestors = BTreeSet::new();
+        let mut unvisited = vec![];
+        for frontier in frontiers {
+            let frontier = Self::get_p_diff_reference::<Retriever>(frontier.clone())?;
+            if let Some(parents) = frontier.parents {
+                unvisited.extend(parents);
+            }
+        }
+        while let Some(hash) = unvisited.pop() {
+            if hash == NULL_NODE() || !ancestors.insert(hash.clone()) {
+                continue;
+            }
+            if let Some(parents) = Self::get_p_diff_reference::<Retriever>(hash)?.parents {
+                unvisited.extend(parents);
+            }
+        }
+
+        for ancestor in ancestors.iter() {
+            self.entry_map.remove(ancestor);
+            if let Some(chunks) = snapshots.get(ancestor) {
+                for chunk in chunks {
+                    self.entry_map.remove(chunk);
+                }
+            }
+        }
+        for reference in self.entry_map.values_mut() {
+            if let Some(parents) = &reference.parents {
+                let kept = parents
+                    .iter()
+                    .filter(|parent| !ancestors.contains(*parent))
+                    .cloned()
+                    .collect::<Vec<Hash>>();
+                reference.parents = if kept.is_empty() { None } else { Some(kept) };
+            }
+        }
+        Ok(())
+    }
+
     fn handle_parents(
         &mut self,
         current_diff: PerspectiveDiffEntryReference,
@@ -312,7 +370,7 @@ impl Workspace {
         self.sort_graph()?;
         //println!("===PerspectiveDiffSunc.build_diffs(): Got unexplored side branches parent: {:#?}", self.unexplored_side_branches.iter().map(|x| hash_to_node_id(x.to_owned())).collect::<Vec<_>>());
 
-        while self.unexplored_side_branches.len() > 0 {
+        while !self.unexplored_side_branches.is_empty() {
             let unexplored_side_branch = self
                 .unexplored_side_branches
                 .iter()
@@ -648,35 +706,32 @@ impl Workspace {
         Retriever::get(address)
     }
 
-    fn get_snapshot(
+    fn get_snapshot<Retriever: PerspectiveDiffRetreiver>(
         address: PerspectiveDiffEntryReference,
     ) -> SocialContextResult<Option<Snapshot>> {
         debug!("===Workspace.get_snapshot(): Function start");
         let fn_start = get_now()?.time();
 
-        let mut snapshot_links = get_links(
-            hash_entry(address)?,
+        let mut snapshot_links = Retriever::get_link_targets(
+            Retriever::hash_entry(address)?.into(),
             LinkTypes::Snapshot,
             Some(LinkTag::new("snapshot")),
         )?;
 
-        if snapshot_links.len() > 0 {
-            let snapshot = get(
-                snapshot_links
-                    .remove(0)
-                    .target
-                    .into_entry_hash()
-                    .expect("Could not get entry hash"),
-                GetOptions::latest(),
-            )?
-            .ok_or(SocialContextError::InternalError(
-                "Workspace::get_snapshot: Could not find entry while populating search",
-            ))?
-            .entry()
-            .to_app_option::<Snapshot>()?
-            .ok_or(SocialContextError::InternalError(
-                "Expected element to contain app entry data",
-            ))?;
+        if !snapshot_links.is_empty() {
+            let snapshot_hash = snapshot_links.remove(0).into_entry_hash().ok_or(
+                SocialContextError::InternalError(
+                    "Workspace::get_snapshot: Snapshot link target is not an entry hash",
+                ),
+            )?;
+            let snapshot = Retriever::get_entry::<Snapshot>(snapshot_hash.clone()).map_err(
+                |error| match error {
+                    SocialContextError::EntryNotFound(_) => {
+                        SocialContextError::SnapshotMissing(snapshot_hash)
+                    }
+                    error => error,
+                },
+            )?;
 
             let fn_end = get_now()?.time();
             debug!("===Workspace.get_snapshot() - Profiling: Took: {} to complete get_snapshot() function", (fn_end - fn_start).num_milliseconds());
@@ -758,17 +813,32 @@ impl Workspace {
         debug!("===Workspace.squashed_diff(): Function start");
         let fn_start = get_now()?.time();
 
+        //Replay the diffs in causal order and only keep the last change of every link,
+        //so a link that got added and removed again does not show up as an addition
+        let mut changes = BTreeMap::new();
+        for (_key, value) in topo_sort_subgraph(&self.entry_map)? {
+            if value.diff == NULL_NODE() {
+                continue;
+            }
+            let diff_entry = get_reference_diff::<Retriever>(&value)?;
+            for addition in diff_entry.additions {
+                changes.insert(addition, true);
+            }
+            for removal in diff_entry.removals {
+                changes.insert(removal, false);
+            }
+        }
+
         let mut out = PerspectiveDiff {
             additions: vec![],
             removals: vec![],
         };
-        for (_key, value) in self.entry_map.iter() {
-            if value.diff == NULL_NODE() {
-                continue;
+        for (link, is_addition) in changes {
+            if is_addition {
+                out.additions.push(link);
+            } else {
+                out.removals.push(link);
             }
-            let diff_entry = Retriever::get::<PerspectiveDiff>(value.diff.clone())?;
-            out.additions.append(&mut diff_entry.additions.clone());
-            out.removals.append(&mut diff_entry.removals.clone());
         }
 
         let fn_end = get_now()?.time();
@@ -0,0 +1,125 @@
+use hdk::prelude::*;
+use perspective_diff_sync_integrity::membership::{
+    Invitation, Membership, ReceivedInvitation, Role,
+};
+use perspective_diff_sync_integrity::properties::DnaProperties;
+use perspective_diff_sync_integrity::{Anchor, EntryTypes, LinkTypes, UnitEntryTypes};
+
+use crate::errors::SocialContextResult;
+use crate::utils::get_now;
+use crate::Hash;
+
+//Invitations and memberships are signed by the client with the DID of their author,
+//the zome only stores and indexes them. Invitations are linked from the anchor of the invited
+//DID, memberships from the members anchor.
+
+fn get_members_anchor() -> Anchor {
+    Anchor("members".to_string())
+}
+
+fn get_linked_records(base: EntryHash, tag: &str) -> SocialContextResult<Vec<Record>> {
+    let mut records = vec![];
+    for link in get_links(base, LinkTypes::Membership, Some(LinkTag::new(tag)))? {
+        if let Some(target) = link.target.into_action_hash() {
+            if let Some(record) = get(target, GetOptions::latest())? {
+                records.push(record);
+            }
+        }
+    }
+    Ok(records)
+}
+
+pub fn create_invitation(invitation: Invitation) -> SocialContextResult<Hash> {
+    let did_anchor = Anchor(invitation.data.did.clone());
+    create_entry(EntryTypes::Anchor(did_anchor.clone()))?;
+    let invitation = create_entry(EntryTypes::Invitation(invitation))?;
+    create_link(
+        hash_entry(did_anchor)?,
+        invitation.clone(),
+        LinkTypes::Membership,
+        LinkTag::new("invitation"),
+    )?;
+    Ok(invitation)
+}
+
+pub fn get_invitations(did: String) -> SocialContextResult<Vec<ReceivedInvitation>> {
+    let mut invitations = vec![];
+    for record in get_linked_records(hash_entry(Anchor(did))?, "invitation")? {
+        if let Some(invitation) = record.entry().to_app_option::<Invitation>()? {
+            invitations.push(ReceivedInvitation {
+                hash: record.action_address().clone(),
+                invitation,
+            });
+        }
+    }
+    Ok(invitations)
+}
+
+pub fn join(membership: Membership) -> SocialContextResult<Hash> {
+    let members_anchor = get_members_anchor();
+    create_entry(EntryTypes::Anchor(members_anchor.clone()))?;
+    let membership = create_entry(EntryTypes::Membership(membership))?;
+    create_link(
+        hash_entry(members_anchor)?,
+        membership.clone(),
+        LinkTypes::Membership,
+        LinkTag::new("membership"),
+    )?;
+    Ok(membership)
+}
+
+/// Memberships whose invitation has not expired yet
+pub fn get_members() -> SocialContextResult<Vec<Membership>> {
+    let mut members = vec![];
+    for record in get_linked_records(hash_entry(get_members_anchor())?, "membership")? {
+        if let Some(membership) = record.entry().to_app_option::<Membership>()? {
+            if holds_now(&membership)? {
+                members.push(membership);
+            }
+        }
+    }
+    Ok(members)
+}
+
+/// Whether the Invitation a Membership accepted still holds, like validation checks it
+fn holds_now(membership: &Membership) -> SocialContextResult<bool> {
+    let invitation = match &membership.invitation {
+        Some(invitation) => invitation.clone(),
+        None => return Ok(true),
+    };
+    let now = get_now()?;
+    match get(invitation, GetOptions::latest())? {
+        Some(record) => Ok(record
+            .entry()
+            .to_app_option::<Invitation>()?
+            .iter()
+            .any(|invitation| invitation.holds_at(&now))),
+        None => Ok(false),
+    }
+}
+
+/// Role of our latest Membership, which is also the one validation judges our commits by
+pub fn my_role() -> SocialContextResult<Option<Role>> {
+    let records = query(
+        QueryFilter::new()
+            .entry_type(UnitEntryTypes::Membership.try_into()?)
+            .include_entries(true)
+            .descending(),
+    )?;
+    let membership = match records.first() {
+        Some(record) => record.entry.to_app_option::<Membership>()?,
+        None => None,
+    };
+    match membership {
+        Some(membership) if holds_now(&membership)? => Ok(Some(membership.data.role)),
+        _ => Ok(None),
+    }
+}
+
+/// Whether we can commit, which everyone can in neighbourhoods without an admin_did
+pub fn can_write(properties: &DnaProperties) -> SocialContextResult<bool> {
+    if properties.admin_did.is_none() {
+        return Ok(true);
+    }
+    Ok(my_role()?.iter().any(|role| role.can_write()))
+}
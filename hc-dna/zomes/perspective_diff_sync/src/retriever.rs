@@ -1,35 +1,70 @@
-use crate::Hash;
 use crate::errors::SocialContextResult;
-use hdk::prelude::*;
+use crate::Hash;
 use chrono::{DateTime, Utc};
+use hdk::prelude::*;
 
+pub mod encryption;
 pub mod holochain;
 pub mod mock;
 
 pub use holochain::HolochainRetreiver;
 pub use mock::*;
-use perspective_diff_sync_integrity::{LocalHashReference, HashReference};
+use perspective_diff_sync_integrity::encryption::SealedPerspectiveDiff;
+use perspective_diff_sync_integrity::{
+    HashReference, LinkTypes, LocalHashReference, LocalPeerSeen, LocalPendingSnapshot,
+    LocalPerspectiveCache,
+};
 
 pub trait PerspectiveDiffRetreiver {
-    fn get<T>(hash: Hash) -> SocialContextResult<T> 
-        where
+    fn get<T>(hash: Hash) -> SocialContextResult<T>
+    where
+        T: TryFrom<SerializedBytes, Error = SerializedBytesError>;
+
+    fn get_entry<T>(hash: EntryHash) -> SocialContextResult<T>
+    where
         T: TryFrom<SerializedBytes, Error = SerializedBytesError>;
 
-    fn get_with_timestamp<T>(hash: Hash) -> SocialContextResult<(T, DateTime<Utc>)> 
-        where
+    fn get_with_timestamp<T>(hash: Hash) -> SocialContextResult<(T, DateTime<Utc>)>
+    where
         T: TryFrom<SerializedBytes, Error = SerializedBytesError>;
 
+    fn get_author(hash: Hash) -> SocialContextResult<AgentPubKey>;
+
     fn create_entry<I, E: std::fmt::Debug, E2>(entry: I) -> SocialContextResult<Hash>
-        where
+    where
         ScopedEntryDefIndex: for<'a> TryFrom<&'a I, Error = E2>,
         EntryVisibility: for<'a> From<&'a I>,
         Entry: TryFrom<I, Error = E>,
         WasmError: From<E>,
         WasmError: From<E2>;
+    fn hash_entry<I, E>(entry: I) -> SocialContextResult<EntryHash>
+    where
+        Entry: TryFrom<I, Error = E>,
+        WasmError: From<E>;
+    fn create_link(
+        base: AnyLinkableHash,
+        target: AnyLinkableHash,
+        link_type: LinkTypes,
+        tag: LinkTag,
+    ) -> SocialContextResult<()>;
+    fn get_link_targets(
+        base: AnyLinkableHash,
+        link_type: LinkTypes,
+        tag: Option<LinkTag>,
+    ) -> SocialContextResult<Vec<AnyLinkableHash>>;
     fn current_revision() -> SocialContextResult<Option<LocalHashReference>>;
     fn latest_revision() -> SocialContextResult<Option<HashReference>>;
     fn update_current_revision(hash: Hash, timestamp: DateTime<Utc>) -> SocialContextResult<()>;
     fn update_latest_revision(hash: Hash, timestamp: DateTime<Utc>) -> SocialContextResult<()>;
+    fn commits_since(since: DateTime<Utc>) -> SocialContextResult<usize>;
+    fn perspective_cache() -> SocialContextResult<Option<LocalPerspectiveCache>>;
+    fn update_perspective_cache(cache: LocalPerspectiveCache) -> SocialContextResult<()>;
+    fn peers_seen_since(since: DateTime<Utc>) -> SocialContextResult<Vec<LocalPeerSeen>>;
+    /// Remembers a broadcast of a peer, so it counts as live until the liveness timeout passed
+    fn record_peer_seen(peer: LocalPeerSeen) -> SocialContextResult<()>;
+    /// Our revisions whose snapshot has not been created yet, oldest first
+    fn pending_snapshots() -> SocialContextResult<Vec<Hash>>;
+    fn update_pending_snapshot(pending: LocalPendingSnapshot) -> SocialContextResult<()>;
+    /// Encrypts a diff entry with the group key, `get` transparently unseals such entries again
+    fn seal(data: SerializedBytes) -> SocialContextResult<SealedPerspectiveDiff>;
 }
-
-
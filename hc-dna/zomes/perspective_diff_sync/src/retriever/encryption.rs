@@ -0,0 +1,227 @@
+use hdk::prelude::*;
+use perspective_diff_sync_integrity::encryption::{
+    group_key_ref, EncryptionKey, GroupKeyWrap, SealedPerspectiveDiff,
+};
+use perspective_diff_sync_integrity::properties::DnaProperties;
+use perspective_diff_sync_integrity::{Anchor, EntryTypes, LinkTypes};
+
+use super::holochain::get_active_agents;
+use crate::errors::{SocialContextError, SocialContextResult};
+use crate::membership::{get_members, my_role};
+use crate::telepresence::status::{get_agents_did_key, get_my_did};
+
+//Diffs of an encrypted neighbourhood are sealed with a single symmetric group key, kept under
+//the reference `group_key_ref` derives from the DNA hash. Only the admin DID creates it, and every
+//member wraps it for the X25519 key of each member that joins after them, so the key itself
+//never touches the DHT unencrypted.
+//Links in sealed diffs can only be checked by members, so validation does not verify their
+//signatures and an encrypted neighbourhood can't have a link schema.
+
+fn get_group_key_anchor() -> Anchor {
+    Anchor("group_key".to_string())
+}
+
+fn get_linked_entry<T>(base: AnyLinkableHash, link_type: LinkTypes) -> SocialContextResult<Vec<T>>
+where
+    T: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+{
+    let mut entries = vec![];
+    for link in get_links(base, link_type, None)? {
+        if let Some(target) = link.target.into_entry_hash() {
+            if let Some(record) = get(target, GetOptions::latest())? {
+                if let Some(entry) = record.entry().to_app_option::<T>()? {
+                    entries.push(entry);
+                }
+            }
+        }
+    }
+    Ok(entries)
+}
+
+pub fn agent_encryption_key(agent: AgentPubKey) -> SocialContextResult<Option<X25519PubKey>> {
+    Ok(
+        get_linked_entry::<EncryptionKey>(agent.into(), LinkTypes::EncryptionKey)?
+            .pop()
+            .map(|key| key.0),
+    )
+}
+
+/// Our X25519 key, which is created and published the first time it is needed
+pub fn my_encryption_key() -> SocialContextResult<X25519PubKey> {
+    let me = agent_info()?.agent_latest_pubkey;
+    if let Some(key) = agent_encryption_key(me.clone())? {
+        return Ok(key);
+    }
+    let key = EncryptionKey(create_x25519_keypair()?);
+    create_entry(EntryTypes::EncryptionKey(key.clone()))?;
+    create_link(
+        me,
+        hash_entry(key.clone())?,
+        LinkTypes::EncryptionKey,
+        LinkTag::new("encryption_key"),
+    )?;
+    Ok(key.0)
+}
+
+fn group_key_wraps(agent: AgentPubKey) -> SocialContextResult<Vec<GroupKeyWrap>> {
+    get_linked_entry::<GroupKeyWrap>(agent.into(), LinkTypes::GroupKey)
+}
+
+fn wrap_group_key(
+    key_ref: XSalsa20Poly1305KeyRef,
+    agent: AgentPubKey,
+    recipient: X25519PubKey,
+) -> SocialContextResult<()> {
+    let sender = my_encryption_key()?;
+    let wrap = GroupKeyWrap {
+        wrapped: x_salsa20_poly1305_shared_secret_export(sender, recipient, key_ref.clone())?,
+        key_ref,
+        sender,
+        recipient,
+    };
+    create_entry(EntryTypes::GroupKeyWrap(wrap.clone()))?;
+    let wrap_hash = hash_entry(wrap)?;
+    create_link(
+        agent,
+        wrap_hash.clone(),
+        LinkTypes::GroupKey,
+        LinkTag::new("group_key"),
+    )?;
+    create_link(
+        hash_entry(get_group_key_anchor())?,
+        wrap_hash,
+        LinkTypes::GroupKey,
+        LinkTag::new("group_key"),
+    )?;
+    Ok(())
+}
+
+/// Loads the group key from the wrap another member created for us into our keystore.
+/// Returns None if nobody shared the key with us yet. This only reads, since it runs whenever a
+/// render or pull comes across a sealed diff.
+fn ingest_group_key(
+    key_ref: &XSalsa20Poly1305KeyRef,
+) -> SocialContextResult<Option<XSalsa20Poly1305KeyRef>> {
+    let me = agent_info()?.agent_latest_pubkey;
+    //Without a published encryption key nobody could have wrapped the key for us
+    let my_key = match agent_encryption_key(me.clone())? {
+        Some(my_key) => my_key,
+        None => return Ok(None),
+    };
+    let wrap = group_key_wraps(me)?
+        .into_iter()
+        .find(|wrap| wrap.recipient == my_key && &wrap.key_ref == key_ref);
+    match wrap {
+        Some(wrap) => Ok(Some(x_salsa20_poly1305_shared_secret_ingest(
+            my_key,
+            wrap.sender,
+            wrap.wrapped,
+            Some(wrap.key_ref),
+        )?)),
+        None => Ok(None),
+    }
+}
+
+/// The group key, created by us if we are the admin DID and did not create one yet.
+/// Everyone else waits for a member to wrap it for them: two agents creating a key under the
+/// same reference could not read each other's diffs, and validation can't tell the keys apart.
+fn group_key() -> SocialContextResult<XSalsa20Poly1305KeyRef> {
+    let key_ref = group_key_ref()?;
+    if let Some(key_ref) = ingest_group_key(&key_ref)? {
+        return Ok(key_ref);
+    }
+    let admin_did = DnaProperties::load()?.admin_did;
+    if admin_did.is_none() || get_my_did()? != admin_did {
+        return Err(SocialContextError::GroupKeyNotShared);
+    }
+    let anchor = hash_entry(get_group_key_anchor())?;
+    if !get_links(anchor, LinkTypes::GroupKey, None)?.is_empty() {
+        return Err(SocialContextError::GroupKeyNotShared);
+    }
+    create_entry(EntryTypes::Anchor(get_group_key_anchor()))?;
+    let key_ref = x_salsa20_poly1305_shared_secret_create_random(Some(key_ref))?;
+    //Wrapped for ourselves as well, our keystore is not the only place we can recover it from
+    wrap_group_key(
+        key_ref.clone(),
+        agent_info()?.agent_latest_pubkey,
+        my_encryption_key()?,
+    )?;
+    Ok(key_ref)
+}
+
+/// Wraps the group key for every active member that published an encryption key but can't read
+/// our diffs yet. Members are the agents linked to a DID, which with an admin_did also have to
+/// have joined with a Membership for that DID. Returns the agents the key got shared with.
+pub fn share_group_key(properties: &DnaProperties) -> SocialContextResult<Vec<AgentPubKey>> {
+    //Validation rejects the wraps of agents that are no member themselves
+    if properties.admin_did.is_some() && my_role()?.is_none() {
+        return Ok(vec![]);
+    }
+    let key_ref = match group_key() {
+        Ok(key_ref) => key_ref,
+        //We can't share what we don't have, a member that already holds the key will
+        Err(SocialContextError::GroupKeyNotShared) => return Ok(vec![]),
+        Err(error) => return Err(error),
+    };
+    let members = match properties.admin_did {
+        Some(_) => Some(get_members()?),
+        None => None,
+    };
+    let mut shared = vec![];
+    for agent in get_active_agents()? {
+        let did = match get_agents_did_key(agent.clone())? {
+            Some(did) => did,
+            None => continue,
+        };
+        if let Some(members) = &members {
+            let agent_key = agent.to_string();
+            let joined = members
+                .iter()
+                .any(|member| member.author == did && member.data.agent == agent_key);
+            if !joined {
+                continue;
+            }
+        }
+        let recipient = match agent_encryption_key(agent.clone())? {
+            Some(recipient) => recipient,
+            None => continue,
+        };
+        let has_key = group_key_wraps(agent.clone())?
+            .iter()
+            .any(|wrap| wrap.recipient == recipient && wrap.key_ref == key_ref);
+        if !has_key {
+            wrap_group_key(key_ref.clone(), agent.clone(), recipient)?;
+            shared.push(agent);
+        }
+    }
+    Ok(shared)
+}
+
+/// Whether the group key has been shared with the given agent
+pub fn is_member(agent: AgentPubKey) -> SocialContextResult<bool> {
+    let key_ref = group_key_ref()?;
+    Ok(group_key_wraps(agent)?
+        .iter()
+        .any(|wrap| wrap.key_ref == key_ref))
+}
+
+pub fn seal(data: SerializedBytes) -> SocialContextResult<SealedPerspectiveDiff> {
+    let key_ref = group_key()?;
+    let sealed = x_salsa20_poly1305_encrypt(key_ref.clone(), data.bytes().to_vec().into())?;
+    Ok(SealedPerspectiveDiff { key_ref, sealed })
+}
+
+pub fn unseal(sealed: SealedPerspectiveDiff) -> SocialContextResult<SerializedBytes> {
+    let data = match x_salsa20_poly1305_decrypt(sealed.key_ref.clone(), sealed.sealed.clone()) {
+        Ok(Some(data)) => data,
+        //Our keystore does not know the key yet, so load it from the wrap shared with us
+        _ => {
+            ingest_group_key(&sealed.key_ref)?.ok_or(SocialContextError::GroupKeyNotShared)?;
+            x_salsa20_poly1305_decrypt(sealed.key_ref, sealed.sealed)?
+                .ok_or(SocialContextError::UnsealFailed)?
+        }
+    };
+    Ok(SerializedBytes::from(UnsafeBytes::from(
+        data.as_ref().to_vec(),
+    )))
+}
@@ -2,41 +2,58 @@ use std::str::FromStr;
 
 use chrono::{DateTime, NaiveDateTime, Utc};
 use hdk::prelude::*;
+use perspective_diff_sync_integrity::encryption::SealedPerspectiveDiff;
 use perspective_diff_sync_integrity::{
-    Anchor, EntryTypes, HashReference, LinkTypes, LocalHashReference,
+    Anchor, EntryTypes, HashReference, LinkTypes, LocalHashReference, LocalPeerSeen,
+    LocalPendingSnapshot, LocalPerspectiveCache, UnitEntryTypes,
 };
 
+use super::encryption;
 use super::PerspectiveDiffRetreiver;
 use crate::errors::{SocialContextError, SocialContextResult};
+use crate::link_adapter::scribe::latest_sightings;
 use crate::utils::dedup;
 use crate::Hash;
 
 pub struct HolochainRetreiver;
 
+/// Number of our most recent actions searched for snapshots left pending
+const PENDING_SNAPSHOT_ACTIONS: u32 = 1000;
+
 impl PerspectiveDiffRetreiver for HolochainRetreiver {
     fn get<T>(hash: Hash) -> SocialContextResult<T>
     where
         T: TryFrom<SerializedBytes, Error = SerializedBytesError>,
     {
-        get(hash, GetOptions::latest())?
-            .ok_or(SocialContextError::InternalError(
-                "HolochainRetreiver: Could not find entry",
-            ))?
+        let record = get(hash.clone(), GetOptions::latest())?
+            .ok_or(SocialContextError::EntryNotFound(hash.clone().into()))?;
+        //Sealed diffs are unsealed here, so callers read them like any other diff
+        if let Ok(Some(sealed)) = record.entry().to_app_option::<SealedPerspectiveDiff>() {
+            return Ok(T::try_from(encryption::unseal(sealed)?)?);
+        }
+        record
+            .entry()
+            .to_app_option::<T>()?
+            .ok_or(SocialContextError::NoAppEntry(hash.into()))
+    }
+
+    fn get_entry<T>(hash: EntryHash) -> SocialContextResult<T>
+    where
+        T: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+    {
+        get(hash.clone(), GetOptions::latest())?
+            .ok_or(SocialContextError::EntryNotFound(hash.clone().into()))?
             .entry()
             .to_app_option::<T>()?
-            .ok_or(SocialContextError::InternalError(
-                "Expected element to contain app entry data",
-            ))
+            .ok_or(SocialContextError::NoAppEntry(hash.into()))
     }
 
     fn get_with_timestamp<T>(hash: Hash) -> SocialContextResult<(T, DateTime<Utc>)>
     where
         T: TryFrom<SerializedBytes, Error = SerializedBytesError>,
     {
-        let element = get(hash, GetOptions::latest())?;
-        let element = element.ok_or(SocialContextError::InternalError(
-            "HolochainRetreiver: Could not find entry",
-        ))?;
+        let element = get(hash.clone(), GetOptions::latest())?;
+        let element = element.ok_or(SocialContextError::EntryNotFound(hash.clone().into()))?;
         let entry = element.entry();
         let timestamp = element.action().timestamp().0 as u64;
         let duration = std::time::Duration::from_micros(timestamp);
@@ -46,12 +63,16 @@ impl PerspectiveDiffRetreiver for HolochainRetreiver {
         );
         let entry = entry
             .to_app_option::<T>()?
-            .ok_or(SocialContextError::InternalError(
-                "Expected element to contain app entry data",
-            ))?;
+            .ok_or(SocialContextError::NoAppEntry(hash.into()))?;
         Ok((entry, timestamp))
     }
 
+    fn get_author(hash: Hash) -> SocialContextResult<AgentPubKey> {
+        let element = get(hash.clone(), GetOptions::latest())?
+            .ok_or(SocialContextError::EntryNotFound(hash.into()))?;
+        Ok(element.action().author().clone())
+    }
+
     fn create_entry<I, E: std::fmt::Debug, E2>(entry: I) -> SocialContextResult<Hash>
     where
         ScopedEntryDefIndex: for<'a> TryFrom<&'a I, Error = E2>,
@@ -63,6 +84,35 @@ impl PerspectiveDiffRetreiver for HolochainRetreiver {
         create_entry::<I, E, E2>(entry).map_err(|e| SocialContextError::Wasm(e))
     }
 
+    fn hash_entry<I, E>(entry: I) -> SocialContextResult<EntryHash>
+    where
+        Entry: TryFrom<I, Error = E>,
+        WasmError: From<E>,
+    {
+        hash_entry::<I, E>(entry).map_err(SocialContextError::Wasm)
+    }
+
+    fn create_link(
+        base: AnyLinkableHash,
+        target: AnyLinkableHash,
+        link_type: LinkTypes,
+        tag: LinkTag,
+    ) -> SocialContextResult<()> {
+        create_link(base, target, link_type, tag)?;
+        Ok(())
+    }
+
+    fn get_link_targets(
+        base: AnyLinkableHash,
+        link_type: LinkTypes,
+        tag: Option<LinkTag>,
+    ) -> SocialContextResult<Vec<AnyLinkableHash>> {
+        Ok(get_links(base, link_type, tag)?
+            .into_iter()
+            .map(|link| link.target)
+            .collect())
+    }
+
     fn current_revision() -> SocialContextResult<Option<LocalHashReference>> {
         let query = query(
             QueryFilter::new()
@@ -153,6 +203,93 @@ impl PerspectiveDiffRetreiver for HolochainRetreiver {
 
         Ok(())
     }
+
+    fn commits_since(since: DateTime<Utc>) -> SocialContextResult<usize> {
+        let since = since.timestamp_nanos() / 1000;
+        let commits = query(
+            QueryFilter::new()
+                .entry_type(UnitEntryTypes::PerspectiveDiffEntryReference.try_into()?),
+        )?;
+        Ok(commits
+            .iter()
+            .filter(|record| record.action().timestamp().0 >= since)
+            .count())
+    }
+
+    fn perspective_cache() -> SocialContextResult<Option<LocalPerspectiveCache>> {
+        let records = query(
+            QueryFilter::new()
+                .entry_type(UnitEntryTypes::LocalPerspectiveCache.try_into()?)
+                .include_entries(true)
+                .descending(),
+        )?;
+        match records.first() {
+            Some(record) => Ok(record.entry.to_app_option::<LocalPerspectiveCache>()?),
+            None => Ok(None),
+        }
+    }
+
+    fn update_perspective_cache(cache: LocalPerspectiveCache) -> SocialContextResult<()> {
+        create_entry(EntryTypes::LocalPerspectiveCache(cache))?;
+        Ok(())
+    }
+
+    //Liveness has to outlive the zome instance that received the broadcast, so sightings are
+    //kept as private entries on our source chain
+    fn peers_seen_since(since: DateTime<Utc>) -> SocialContextResult<Vec<LocalPeerSeen>> {
+        let records = query(
+            QueryFilter::new()
+                .entry_type(UnitEntryTypes::LocalPeerSeen.try_into()?)
+                .include_entries(true),
+        )?;
+        let mut sightings = vec![];
+        for record in records {
+            if let Some(peer) = record.entry.to_app_option::<LocalPeerSeen>()? {
+                sightings.push(peer);
+            }
+        }
+        Ok(latest_sightings(sightings, since))
+    }
+
+    fn record_peer_seen(peer: LocalPeerSeen) -> SocialContextResult<()> {
+        create_entry(EntryTypes::LocalPeerSeen(peer))?;
+        Ok(())
+    }
+
+    fn pending_snapshots() -> SocialContextResult<Vec<Hash>> {
+        //Only the recent part of our chain gets scanned. A revision left pending for longer just
+        //never gets its snapshot, which the snapshots of later revisions make up for.
+        let head = agent_info()?.chain_head.1;
+        let records = query(
+            QueryFilter::new()
+                .sequence_range(ChainQueryFilterRange::ActionSeqRange(
+                    head.saturating_sub(PENDING_SNAPSHOT_ACTIONS),
+                    head,
+                ))
+                .entry_type(UnitEntryTypes::LocalPendingSnapshot.try_into()?)
+                .include_entries(true),
+        )?;
+        let mut pending = vec![];
+        for record in records {
+            if let Some(update) = record.entry.to_app_option::<LocalPendingSnapshot>()? {
+                if update.pending {
+                    pending.push(update.revision);
+                } else {
+                    pending.retain(|revision| revision != &update.revision);
+                }
+            }
+        }
+        Ok(pending)
+    }
+
+    fn update_pending_snapshot(pending: LocalPendingSnapshot) -> SocialContextResult<()> {
+        create_entry(EntryTypes::LocalPendingSnapshot(pending))?;
+        Ok(())
+    }
+
+    fn seal(data: SerializedBytes) -> SocialContextResult<SealedPerspectiveDiff> {
+        encryption::seal(data)
+    }
 }
 
 fn get_latest_revision_anchor() -> Anchor {
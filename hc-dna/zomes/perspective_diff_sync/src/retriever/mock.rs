@@ -1,10 +1,12 @@
 use chrono::{DateTime, Utc};
 use dot_structures;
 use graphviz_rust;
+use hdk::prelude::holochain_zome_types::x_salsa20_poly1305::nonce::XSalsa20Poly1305Nonce;
 use hdk::prelude::*;
+use perspective_diff_sync_integrity::encryption::SealedPerspectiveDiff;
 use perspective_diff_sync_integrity::{
-    HashReference, LinkExpression, LocalHashReference, PerspectiveDiff,
-    PerspectiveDiffEntryReference,
+    HashReference, LinkExpression, LinkTypes, LocalHashReference, LocalPeerSeen,
+    LocalPendingSnapshot, LocalPerspectiveCache, PerspectiveDiff, PerspectiveDiffEntryReference,
 };
 use sha2::{Digest, Sha256};
 use std::collections::BTreeMap;
@@ -12,6 +14,7 @@ use std::sync::Mutex;
 
 use super::PerspectiveDiffRetreiver;
 use crate::errors::{SocialContextError, SocialContextResult};
+use crate::link_adapter::scribe::latest_sightings;
 use crate::link_adapter::workspace::NULL_NODE;
 use crate::utils::create_link_expression;
 use crate::Hash;
@@ -19,6 +22,15 @@ use crate::Hash;
 #[derive(Debug)]
 pub struct MockPerspectiveGraph {
     pub graph_map: BTreeMap<Hash, SerializedBytes>,
+    pub links: Vec<MockLink>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MockLink {
+    pub base: AnyLinkableHash,
+    pub target: AnyLinkableHash,
+    pub link_type: LinkTypes,
+    pub tag: LinkTag,
 }
 
 impl PerspectiveDiffRetreiver for MockPerspectiveGraph {
@@ -33,9 +45,21 @@ impl PerspectiveDiffRetreiver for MockPerspectiveGraph {
             .get(&hash)
             .expect("Could not find entry in map")
             .to_owned();
+        if let Ok(sealed) = SealedPerspectiveDiff::try_from(value.to_owned()) {
+            let data = sealed.sealed.as_encrypted_data_ref().to_vec();
+            return Ok(T::try_from(SerializedBytes::from(UnsafeBytes::from(data)))?);
+        }
         Ok(T::try_from(value.to_owned())?)
     }
 
+    fn get_entry<T>(hash: EntryHash) -> SocialContextResult<T>
+    where
+        T: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+    {
+        //Mocked entries are stored under an action hash with the same core as their entry hash
+        Self::get(ActionHash::from_raw_36(hash.get_raw_36().to_vec()))
+    }
+
     fn get_with_timestamp<T>(hash: Hash) -> SocialContextResult<(T, DateTime<Utc>)>
     where
         T: TryFrom<SerializedBytes, Error = SerializedBytesError>,
@@ -47,7 +71,18 @@ impl PerspectiveDiffRetreiver for MockPerspectiveGraph {
             .get(&hash)
             .expect("Could not find entry in map")
             .to_owned();
-        Ok((T::try_from(value.to_owned())?, Utc::now()))
+        let created = CREATED
+            .lock()
+            .expect("Could not get lock on CREATED")
+            .get(&hash)
+            .cloned()
+            .unwrap_or_else(Utc::now);
+        Ok((T::try_from(value.to_owned())?, created))
+    }
+
+    fn get_author(hash: Hash) -> SocialContextResult<AgentPubKey> {
+        let authors = AUTHORS.lock().expect("Could not get lock on AUTHORS");
+        Ok(authors.get(&hash).cloned().unwrap_or_else(default_agent))
     }
 
     fn create_entry<I, E: std::fmt::Debug, E2>(entry: I) -> SocialContextResult<Hash>
@@ -67,18 +102,86 @@ impl PerspectiveDiffRetreiver for MockPerspectiveGraph {
             Entry::App(bytes) => bytes,
             _ => panic!("Should not get any entry except app"),
         };
-        let bytes = sb.bytes();
-
-        let mut hasher = Sha256::new();
-        hasher.update(bytes);
-        let mut result = hasher.finalize().as_slice().to_owned();
-        result.append(&mut vec![0xdb, 0xdb, 0xdb, 0xdb]);
 
-        let hash = ActionHash::from_raw_36(result);
+        if PerspectiveDiffEntryReference::try_from(sb.0.clone()).is_ok() {
+            COMMITS
+                .lock()
+                .expect("Could not get lock on COMMITS")
+                .push(Utc::now());
+        }
+        //Like on the DHT, the same entry gets a different action hash for every author
+        let entry_hash = ActionHash::from_raw_36(mock_hash(sb.bytes()));
+        let agent = MOCK_AGENT
+            .lock()
+            .expect("Could not get lock on MOCK_AGENT")
+            .clone();
+        let mut created = CREATED.lock().expect("Could not get lock on CREATED");
+        if agent == default_agent() {
+            created.entry(entry_hash.clone()).or_insert_with(Utc::now);
+            object_store.graph_map.insert(entry_hash.clone(), sb.0);
+            return Ok(entry_hash);
+        }
+        let hash = ActionHash::from_raw_36(mock_hash(
+            &[sb.bytes().as_slice(), agent.get_raw_36()].concat(),
+        ));
+        AUTHORS
+            .lock()
+            .expect("Could not get lock on AUTHORS")
+            .insert(hash.clone(), agent);
+        created.entry(hash.clone()).or_insert_with(Utc::now);
+        object_store.graph_map.insert(entry_hash, sb.0.clone());
         object_store.graph_map.insert(hash.clone(), sb.0);
         Ok(hash)
     }
 
+    fn hash_entry<I, E>(entry: I) -> SocialContextResult<EntryHash>
+    where
+        Entry: TryFrom<I, Error = E>,
+        WasmError: From<E>,
+    {
+        let entry: Entry = entry.try_into().map_err(WasmError::from)?;
+        let sb = match entry {
+            Entry::App(bytes) => bytes,
+            _ => panic!("Should not get any entry except app"),
+        };
+        Ok(EntryHash::from_raw_36(mock_hash(sb.bytes())))
+    }
+
+    fn create_link(
+        base: AnyLinkableHash,
+        target: AnyLinkableHash,
+        link_type: LinkTypes,
+        tag: LinkTag,
+    ) -> SocialContextResult<()> {
+        let mut object_store = GLOBAL_MOCKED_GRAPH
+            .lock()
+            .expect("Could not get lock on OBJECT_STORE");
+        object_store.links.push(MockLink {
+            base,
+            target,
+            link_type,
+            tag,
+        });
+        Ok(())
+    }
+
+    fn get_link_targets(
+        base: AnyLinkableHash,
+        link_type: LinkTypes,
+        tag: Option<LinkTag>,
+    ) -> SocialContextResult<Vec<AnyLinkableHash>> {
+        let object_store = GLOBAL_MOCKED_GRAPH
+            .lock()
+            .expect("Could not get lock on OBJECT_STORE");
+        Ok(object_store
+            .links
+            .iter()
+            .filter(|link| link.base == base && link.link_type == link_type)
+            .filter(|link| tag.as_ref().map(|tag| link.tag == *tag).unwrap_or(true))
+            .map(|link| link.target.clone())
+            .collect())
+    }
+
     fn current_revision() -> SocialContextResult<Option<LocalHashReference>> {
         let revision = CURRENT_REVISION
             .lock()
@@ -114,6 +217,81 @@ impl PerspectiveDiffRetreiver for MockPerspectiveGraph {
         *revision = Some(hash);
         Ok(())
     }
+
+    fn commits_since(since: DateTime<Utc>) -> SocialContextResult<usize> {
+        let commits = COMMITS.lock().expect("Could not get lock on COMMITS");
+        Ok(commits.iter().filter(|commit| **commit >= since).count())
+    }
+
+    fn perspective_cache() -> SocialContextResult<Option<LocalPerspectiveCache>> {
+        let cache = PERSPECTIVE_CACHE
+            .lock()
+            .expect("Could not get lock on PERSPECTIVE_CACHE");
+        Ok(cache.clone())
+    }
+
+    fn update_perspective_cache(new_cache: LocalPerspectiveCache) -> SocialContextResult<()> {
+        let mut cache = PERSPECTIVE_CACHE
+            .lock()
+            .expect("Could not get lock on PERSPECTIVE_CACHE");
+        *cache = Some(new_cache);
+        Ok(())
+    }
+
+    fn peers_seen_since(since: DateTime<Utc>) -> SocialContextResult<Vec<LocalPeerSeen>> {
+        let peers = PEERS_SEEN.lock().expect("Could not get lock on PEERS_SEEN");
+        Ok(latest_sightings(peers.iter().cloned(), since))
+    }
+
+    fn record_peer_seen(peer: LocalPeerSeen) -> SocialContextResult<()> {
+        let mut peers = PEERS_SEEN.lock().expect("Could not get lock on PEERS_SEEN");
+        peers.push(peer);
+        Ok(())
+    }
+
+    fn pending_snapshots() -> SocialContextResult<Vec<Hash>> {
+        let pending = PENDING_SNAPSHOTS
+            .lock()
+            .expect("Could not get lock on PENDING_SNAPSHOTS");
+        Ok(pending.clone())
+    }
+
+    fn update_pending_snapshot(update: LocalPendingSnapshot) -> SocialContextResult<()> {
+        let mut pending = PENDING_SNAPSHOTS
+            .lock()
+            .expect("Could not get lock on PENDING_SNAPSHOTS");
+        if update.pending {
+            pending.push(update.revision);
+        } else {
+            pending.retain(|revision| revision != &update.revision);
+        }
+        Ok(())
+    }
+
+    /// Stands in for the group key encryption, the data is kept readable as is
+    fn seal(data: SerializedBytes) -> SocialContextResult<SealedPerspectiveDiff> {
+        Ok(SealedPerspectiveDiff {
+            key_ref: XSalsa20Poly1305KeyRef::from([0; 32]),
+            sealed: XSalsa20Poly1305EncryptedData::new(
+                XSalsa20Poly1305Nonce::from([0; 24]),
+                data.bytes().to_vec(),
+            ),
+        })
+    }
+}
+
+/// Hashes bytes the way mocked entries get their hashes: sha256 plus the 0xdb location bytes
+/// Agent every mocked action is authored by, unless MOCK_AGENT is set to another one
+pub fn default_agent() -> AgentPubKey {
+    AgentPubKey::from_raw_36(vec![0xdb; 36])
+}
+
+pub fn mock_hash(bytes: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    let mut result = hasher.finalize().as_slice().to_owned();
+    result.append(&mut vec![0xdb, 0xdb, 0xdb, 0xdb]);
+    result
 }
 
 pub struct GraphInput {
@@ -240,6 +418,7 @@ impl MockPerspectiveGraph {
     pub fn new(graph_input: GraphInput) -> MockPerspectiveGraph {
         let mut graph = MockPerspectiveGraph {
             graph_map: BTreeMap::new(),
+            links: vec![],
         };
 
         for n in 0..graph_input.nodes {
@@ -281,6 +460,7 @@ impl MockPerspectiveGraph {
             dot_structures::Graph::DiGraph { stmts, .. } => {
                 let mut graph = MockPerspectiveGraph {
                     graph_map: BTreeMap::new(),
+                    links: vec![],
                 };
 
                 let mut hashes = Vec::<Hash>::new();
@@ -322,11 +502,7 @@ impl MockPerspectiveGraph {
 
                     //Create a mock hash for the fake diff
                     let ref_sb = SerializedBytes::try_from(diff.clone())?;
-                    let mut hasher = Sha256::new();
-                    hasher.update(ref_sb.bytes());
-                    let mut result = hasher.finalize().as_slice().to_owned();
-                    result.append(&mut vec![0xdb, 0xdb, 0xdb, 0xdb]);
-                    let diff_hash = ActionHash::from_raw_36(result);
+                    let diff_hash = ActionHash::from_raw_36(mock_hash(ref_sb.bytes()));
 
                     //Create the diff reference
                     let diff_ref = PerspectiveDiffEntryReference::new(
@@ -357,6 +533,16 @@ lazy_static! {
         }));
     pub static ref CURRENT_REVISION: Mutex<Option<Hash>> = Mutex::new(None);
     pub static ref LATEST_REVISION: Mutex<Option<Hash>> = Mutex::new(None);
+    pub static ref PERSPECTIVE_CACHE: Mutex<Option<LocalPerspectiveCache>> = Mutex::new(None);
+    pub static ref PEERS_SEEN: Mutex<Vec<LocalPeerSeen>> = Mutex::new(vec![]);
+    pub static ref PENDING_SNAPSHOTS: Mutex<Vec<Hash>> = Mutex::new(vec![]);
+    /// Agent that creates entries through the mock, to simulate entries of several authors
+    pub static ref MOCK_AGENT: Mutex<AgentPubKey> = Mutex::new(default_agent());
+    pub static ref AUTHORS: Mutex<BTreeMap<Hash, AgentPubKey>> = Mutex::new(BTreeMap::new());
+    /// Creation times of the diff references written through the mock
+    pub static ref COMMITS: Mutex<Vec<DateTime<Utc>>> = Mutex::new(vec![]);
+    /// Time every entry got first created through the mock
+    pub static ref CREATED: Mutex<BTreeMap<Hash, DateTime<Utc>>> = Mutex::new(BTreeMap::new());
 }
 
 #[test]
@@ -90,22 +90,16 @@ pub fn create_did_pub_key_link(did: String) -> SocialContextResult<()> {
 pub fn get_my_did() -> SocialContextResult<Option<String>> {
     let mut did_links = get_links(agent_info()?.agent_latest_pubkey, LinkTypes::DidLink, None)?;
     if did_links.len() > 0 {
-        let did = get(
-            did_links
-                .remove(0)
-                .target
-                .into_entry_hash()
-                .expect("Could not get entry_hash"),
-            GetOptions::latest(),
-        )?
-        .ok_or(SocialContextError::InternalError(
-            "Could not find did entry for given did entry reference",
-        ))?
-        .entry()
-        .to_app_option::<Anchor>()?
-        .ok_or(SocialContextError::InternalError(
-            "Expected element to contain app entry data",
-        ))?;
+        let did_hash = did_links
+            .remove(0)
+            .target
+            .into_entry_hash()
+            .expect("Could not get entry_hash");
+        let did = get(did_hash.clone(), GetOptions::latest())?
+            .ok_or(SocialContextError::EntryNotFound(did_hash.clone().into()))?
+            .entry()
+            .to_app_option::<Anchor>()?
+            .ok_or(SocialContextError::NoAppEntry(did_hash.into()))?;
         Ok(Some(did.0))
     } else {
         Ok(None)
@@ -129,22 +123,16 @@ pub fn get_dids_agent_key(did: String) -> SocialContextResult<Option<AgentPubKey
 pub fn get_agents_did_key(agent: AgentPubKey) -> SocialContextResult<Option<String>> {
     let mut did_links = get_links(agent, LinkTypes::DidLink, None)?;
     if did_links.len() > 0 {
-        let did = get(
-            did_links
-                .remove(0)
-                .target
-                .into_entry_hash()
-                .expect("Could not get entry_hash"),
-            GetOptions::latest(),
-        )?
-        .ok_or(SocialContextError::InternalError(
-            "Could not find did entry for given did entry reference",
-        ))?
-        .entry()
-        .to_app_option::<Anchor>()?
-        .ok_or(SocialContextError::InternalError(
-            "Expected element to contain app entry data",
-        ))?;
+        let did_hash = did_links
+            .remove(0)
+            .target
+            .into_entry_hash()
+            .expect("Could not get entry_hash");
+        let did = get(did_hash.clone(), GetOptions::latest())?
+            .ok_or(SocialContextError::EntryNotFound(did_hash.clone().into()))?
+            .entry()
+            .to_app_option::<Anchor>()?
+            .ok_or(SocialContextError::NoAppEntry(did_hash.into()))?;
         Ok(Some(did.0))
     } else {
         Ok(None)
@@ -24,10 +24,6 @@ pub fn dedup<T: Eq + Hash + Clone>(vs: &Vec<T>) -> Vec<T> {
     hs.into_iter().collect()
 }
 
-pub(crate) fn err(reason: &str) -> WasmError {
-    wasm_error!(WasmErrorInner::Host(String::from(reason)))
-}
-
 #[allow(dead_code)]
 pub fn create_link_expression(source: &str, target: &str) -> LinkExpression {
     LinkExpression {
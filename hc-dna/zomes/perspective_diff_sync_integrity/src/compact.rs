@@ -0,0 +1,287 @@
+use chrono::{DateTime, Utc};
+use hdi::prelude::*;
+use std::collections::BTreeMap;
+
+use crate::{ExpressionProof, LinkExpression, PerspectiveDiff, Triple};
+
+/// Version written into every CompactPerspectiveDiff, bumped whenever the payload layout changes
+pub const COMPACT_DIFF_VERSION: u8 = 1;
+
+/// Bytes a single link may take up in a payload, which bounds the payload of a chunk
+pub const MAX_PAYLOAD_BYTES_PER_LINK: usize = 16 * 1024;
+
+/// Holochain's entry size limit, which no diff written in plain could exceed either
+pub const MAX_PAYLOAD_BYTES: usize = 4_000_000;
+
+/// Largest payload a diff of at most chunk_size links may be written with or inflate to
+pub fn payload_limit(chunk_size: u16) -> usize {
+    (chunk_size as usize * MAX_PAYLOAD_BYTES_PER_LINK).min(MAX_PAYLOAD_BYTES)
+}
+
+///A PerspectiveDiff in which every string is stored once in a table and links refer to it by index
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes, PartialEq)]
+pub struct CompactPerspectiveDiff {
+    pub version: u8,
+    pub compressed: bool,
+    #[serde(with = "serde_bytes")]
+    pub payload: Vec<u8>,
+}
+
+app_entry!(CompactPerspectiveDiff);
+
+#[derive(Debug, Serialize, Deserialize, SerializedBytes)]
+struct CompactPayload {
+    strings: Vec<String>,
+    additions: Vec<CompactLink>,
+    removals: Vec<CompactLink>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CompactLink {
+    author: u32,
+    source: Option<u32>,
+    target: Option<u32>,
+    predicate: Option<u32>,
+    timestamp: DateTime<Utc>,
+    signature: String,
+    key: u32,
+}
+
+#[derive(Default)]
+struct StringTable {
+    strings: Vec<String>,
+    positions: BTreeMap<String, u32>,
+}
+
+impl StringTable {
+    fn intern(&mut self, string: &str) -> u32 {
+        if let Some(position) = self.positions.get(string) {
+            return *position;
+        }
+        let position = self.strings.len() as u32;
+        self.strings.push(string.to_string());
+        self.positions.insert(string.to_string(), position);
+        position
+    }
+
+    fn intern_link(&mut self, link: &LinkExpression) -> CompactLink {
+        CompactLink {
+            author: self.intern(&link.author),
+            source: link.data.source.as_ref().map(|source| self.intern(source)),
+            target: link.data.target.as_ref().map(|target| self.intern(target)),
+            predicate: link
+                .data
+                .predicate
+                .as_ref()
+                .map(|predicate| self.intern(predicate)),
+            timestamp: link.timestamp,
+            signature: link.proof.signature.clone(),
+            key: self.intern(&link.proof.key),
+        }
+    }
+}
+
+fn lookup(strings: &[String], position: u32) -> Result<String, SerializedBytesError> {
+    strings.get(position as usize).cloned().ok_or_else(|| {
+        SerializedBytesError::Deserialize(format!(
+            "CompactPerspectiveDiff refers to missing string {}",
+            position
+        ))
+    })
+}
+
+fn lookup_optional(
+    strings: &[String],
+    position: Option<u32>,
+) -> Result<Option<String>, SerializedBytesError> {
+    position
+        .map(|position| lookup(strings, position))
+        .transpose()
+}
+
+fn expand_link(
+    strings: &[String],
+    link: CompactLink,
+) -> Result<LinkExpression, SerializedBytesError> {
+    Ok(LinkExpression {
+        author: lookup(strings, link.author)?,
+        data: Triple {
+            source: lookup_optional(strings, link.source)?,
+            target: lookup_optional(strings, link.target)?,
+            predicate: lookup_optional(strings, link.predicate)?,
+        },
+        timestamp: link.timestamp,
+        proof: ExpressionProof {
+            signature: link.signature,
+            key: lookup(strings, link.key)?,
+        },
+    })
+}
+
+impl CompactPerspectiveDiff {
+    pub fn encode(
+        diff: &PerspectiveDiff,
+        compress: bool,
+        chunk_size: u16,
+    ) -> Result<Self, SerializedBytesError> {
+        let mut table = StringTable::default();
+        let additions = diff
+            .additions
+            .iter()
+            .map(|link| table.intern_link(link))
+            .collect();
+        let removals = diff
+            .removals
+            .iter()
+            .map(|link| table.intern_link(link))
+            .collect();
+        let payload = SerializedBytes::try_from(CompactPayload {
+            strings: table.strings,
+            additions,
+            removals,
+        })?
+        .bytes()
+        .to_vec();
+        if payload.len() > payload_limit(chunk_size) {
+            return Err(SerializedBytesError::Serialize(format!(
+                "CompactPerspectiveDiff payload of {} bytes exceeds the limit of {}",
+                payload.len(),
+                payload_limit(chunk_size)
+            )));
+        }
+
+        Ok(CompactPerspectiveDiff {
+            version: COMPACT_DIFF_VERSION,
+            compressed: compress,
+            payload: if compress {
+                miniz_oxide::deflate::compress_to_vec(&payload, 6)
+            } else {
+                payload
+            },
+        })
+    }
+
+    /// Inflates at most payload_limit(chunk_size) bytes, so a small entry can't make its
+    /// readers allocate without bound
+    pub fn decode(&self, chunk_size: u16) -> Result<PerspectiveDiff, SerializedBytesError> {
+        if self.version != COMPACT_DIFF_VERSION {
+            return Err(SerializedBytesError::Deserialize(format!(
+                "Unsupported CompactPerspectiveDiff version {}",
+                self.version
+            )));
+        }
+        let limit = payload_limit(chunk_size);
+        let payload = if self.compressed {
+            miniz_oxide::inflate::decompress_to_vec_with_limit(&self.payload, limit).map_err(
+                |_| {
+                    SerializedBytesError::Deserialize(format!(
+                        "CompactPerspectiveDiff payload is not deflate data of at most {} bytes",
+                        limit
+                    ))
+                },
+            )?
+        } else if self.payload.len() > limit {
+            return Err(SerializedBytesError::Deserialize(format!(
+                "CompactPerspectiveDiff payload exceeds the limit of {} bytes",
+                limit
+            )));
+        } else {
+            self.payload.clone()
+        };
+        let payload = CompactPayload::try_from(SerializedBytes::from(UnsafeBytes::from(payload)))?;
+
+        let strings = payload.strings;
+        Ok(PerspectiveDiff {
+            additions: payload
+                .additions
+                .into_iter()
+                .map(|link| expand_link(&strings, link))
+                .collect::<Result<_, _>>()?,
+            removals: payload
+                .removals
+                .into_iter()
+                .map(|link| expand_link(&strings, link))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// Reads diff entry bytes in any of the encodings a diff can be stored in
+pub fn decode_diff(
+    bytes: SerializedBytes,
+    chunk_size: u16,
+) -> Result<PerspectiveDiff, SerializedBytesError> {
+    match PerspectiveDiff::try_from(bytes.clone()) {
+        Ok(diff) => Ok(diff),
+        Err(error) => match CompactPerspectiveDiff::try_from(bytes) {
+            Ok(compact) => compact.decode(chunk_size),
+            Err(_) => Err(error),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(source: &str, target: &str) -> LinkExpression {
+        LinkExpression {
+            author: String::from("did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK"),
+            data: Triple {
+                source: Some(String::from(source)),
+                target: Some(String::from(target)),
+                predicate: Some(String::from("sioc://has_reply")),
+            },
+            timestamp: Utc::now(),
+            proof: ExpressionProof {
+                signature: String::from("00"),
+                key: String::from("#z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK"),
+            },
+        }
+    }
+
+    #[test]
+    fn compact_diffs_roundtrip() {
+        let diff = PerspectiveDiff {
+            additions: (0..50)
+                .map(|i| link("ad4m://self", &format!("literal://number:{}", i)))
+                .collect(),
+            removals: vec![link("literal://number:1", "ad4m://self")],
+        };
+        let plain_size = SerializedBytes::try_from(diff.clone())
+            .unwrap()
+            .bytes()
+            .len();
+
+        for compress in [false, true].iter() {
+            let compact = CompactPerspectiveDiff::encode(&diff, *compress, 51).unwrap();
+            assert!(compact.payload.len() < plain_size);
+
+            let bytes = SerializedBytes::try_from(compact).unwrap();
+            let decoded = decode_diff(bytes, 51).unwrap();
+            assert_eq!(decoded.additions, diff.additions);
+            assert_eq!(decoded.removals, diff.removals);
+        }
+
+        let plain = SerializedBytes::try_from(diff.clone()).unwrap();
+        assert_eq!(decode_diff(plain, 51).unwrap().additions, diff.additions);
+    }
+
+    #[test]
+    fn refuses_to_inflate_beyond_chunk_size() {
+        let bomb = CompactPerspectiveDiff {
+            version: COMPACT_DIFF_VERSION,
+            compressed: true,
+            payload: miniz_oxide::deflate::compress_to_vec(&vec![0; payload_limit(1) + 1], 6),
+        };
+        assert!(bomb.payload.len() < MAX_PAYLOAD_BYTES_PER_LINK);
+        assert!(bomb.decode(1).is_err());
+
+        let diff = PerspectiveDiff {
+            additions: vec![link("ad4m://self", "literal://number:1")],
+            removals: vec![],
+        };
+        let compact = CompactPerspectiveDiff::encode(&diff, true, 1).unwrap();
+        assert_eq!(compact.decode(1).unwrap().additions, diff.additions);
+    }
+}
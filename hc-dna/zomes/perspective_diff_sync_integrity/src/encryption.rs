@@ -0,0 +1,35 @@
+use hdi::prelude::*;
+
+///A diff entry (PerspectiveDiff or CompactPerspectiveDiff) sealed with the group key of the neighbourhood
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes, PartialEq)]
+pub struct SealedPerspectiveDiff {
+    pub key_ref: XSalsa20Poly1305KeyRef,
+    pub sealed: XSalsa20Poly1305EncryptedData,
+}
+
+app_entry!(SealedPerspectiveDiff);
+
+///X25519 public key of an agent, which other members wrap the group key for
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes, PartialEq)]
+pub struct EncryptionKey(pub X25519PubKey);
+
+app_entry!(EncryptionKey);
+
+///The group key, encrypted from the X25519 key of the member sharing it to the X25519 key of the recipient
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes, PartialEq)]
+pub struct GroupKeyWrap {
+    pub key_ref: XSalsa20Poly1305KeyRef,
+    pub sender: X25519PubKey,
+    pub recipient: X25519PubKey,
+    pub wrapped: XSalsa20Poly1305EncryptedData,
+}
+
+app_entry!(GroupKeyWrap);
+
+///Reference every member keeps the group key under. It is derived from the DNA hash, so all wraps
+///of a neighbourhood name the same key and validation rejects wraps of any other key.
+pub fn group_key_ref() -> ExternResult<XSalsa20Poly1305KeyRef> {
+    let mut key_ref = [0; 32];
+    key_ref.copy_from_slice(dna_info()?.hash.get_raw_32());
+    Ok(XSalsa20Poly1305KeyRef::from(key_ref))
+}
@@ -51,6 +51,8 @@ impl PerspectiveDiffEntryReference {
             diff: diff,
             parents: parents,
             diffs_since_snapshot: 0,
+            metadata: None,
+            diff_chunks: None,
         }
     }
 }
@@ -1,7 +1,21 @@
 use chrono::{DateTime, Utc};
 use hdi::prelude::*;
+use std::collections::BTreeMap;
 
+pub mod compact;
+pub mod encryption;
 pub mod impls;
+pub mod membership;
+pub mod moderation;
+pub mod properties;
+pub mod schema;
+pub mod signatures;
+pub mod validation;
+
+use compact::CompactPerspectiveDiff;
+use encryption::{EncryptionKey, GroupKeyWrap, SealedPerspectiveDiff};
+use membership::{Invitation, Membership};
+use moderation::BanList;
 
 #[derive(
     Serialize, Deserialize, Clone, SerializedBytes, Debug, PartialEq, Eq, Hash, Ord, PartialOrd,
@@ -39,6 +53,7 @@ pub struct PerspectiveDiff {
 pub struct HashBroadcast {
     pub reference_hash: HoloHash<holo_hash::hash_type::Action>,
     pub reference: PerspectiveDiffEntryReference,
+    //Empty if the reference lists diff_chunks, those are fetched by the receivers
     pub diff: PerspectiveDiff,
     pub broadcast_author: String,
 }
@@ -57,19 +72,39 @@ impl PerspectiveDiff {
 
 app_entry!(PerspectiveDiff);
 
+///Net state of the perspective at a revision, stored as chunks of additions
 #[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
 pub struct Snapshot {
     pub diff_chunks: Vec<HoloHash<holo_hash::hash_type::Action>>,
+    ///Frontier of the included history, every ancestor of these diffs is included as well.
+    ///Snapshots created by older versions list every included diff instead.
     pub included_diffs: Vec<HoloHash<holo_hash::hash_type::Action>>,
 }
 
 app_entry!(Snapshot);
 
+///Optional information about a commit given by the client
+#[derive(Clone, Debug, Default, Serialize, Deserialize, SerializedBytes, PartialEq, Eq, Hash)]
+#[serde(default)]
+pub struct CommitMetadata {
+    ///DID of the committing agent, set by the zome and not by the client
+    pub author: Option<String>,
+    pub message: Option<String>,
+    pub tags: Vec<String>,
+    pub client_version: Option<String>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes, PartialEq, Eq, Hash)]
 pub struct PerspectiveDiffEntryReference {
     pub diff: HoloHash<holo_hash::hash_type::Action>,
     pub parents: Option<Vec<HoloHash<holo_hash::hash_type::Action>>>,
     pub diffs_since_snapshot: usize,
+    //Entries created before metadata existed don't have this field
+    #[serde(default)]
+    pub metadata: Option<CommitMetadata>,
+    ///Set when the diff was too large for one entry, then diff is the first of these chunks
+    #[serde(default)]
+    pub diff_chunks: Option<Vec<HoloHash<holo_hash::hash_type::Action>>>,
 }
 
 app_entry!(PerspectiveDiffEntryReference);
@@ -103,6 +138,46 @@ pub struct LocalTimestampReference {
 
 app_entry!(LocalTimestampReference);
 
+///Materialized perspective at a given revision, used by render to only apply the diffs after it.
+///The links are kept in ascending order, so pages can be read from it directly.
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct LocalPerspectiveCache {
+    pub revision: HoloHash<holo_hash::hash_type::Action>,
+    pub links: Vec<LinkExpression>,
+    //Caches written before queries were indexed don't have this field
+    #[serde(default)]
+    pub index: Option<LinkIndex>,
+}
+
+///Positions of the links of a LocalPerspectiveCache by their source, predicate and target,
+///each list in ascending order
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct LinkIndex {
+    pub by_source: BTreeMap<String, Vec<u32>>,
+    pub by_predicate: BTreeMap<String, Vec<u32>>,
+    pub by_target: BTreeMap<String, Vec<u32>>,
+}
+
+app_entry!(LocalPerspectiveCache);
+
+///Last time we received a broadcast from a peer, used to elect the scribe among live peers
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes, PartialEq)]
+pub struct LocalPeerSeen {
+    pub did: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+app_entry!(LocalPeerSeen);
+
+///Marks a revision of ours whose snapshot still has to be created, or has been created once pending is false
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes, PartialEq)]
+pub struct LocalPendingSnapshot {
+    pub revision: HoloHash<holo_hash::hash_type::Action>,
+    pub pending: bool,
+}
+
+app_entry!(LocalPendingSnapshot);
+
 #[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
 pub struct Anchor(pub String);
 
@@ -137,6 +212,34 @@ pub struct PullResult {
     pub current_revision: Option<HoloHash<holo_hash::hash_type::Action>>,
 }
 
+///A page of the rendered perspective, next_cursor is None on the last page
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct RenderPage {
+    pub links: Vec<LinkExpression>,
+    pub next_cursor: Option<LinkExpression>,
+    pub revision: HoloHash<holo_hash::hash_type::Action>,
+}
+
+///Whether a snapshot exists for a revision, see snapshot_status
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotStatus {
+    ///The revision is not at a snapshot interval
+    NotScheduled,
+    ///The revision is at a snapshot interval, but its snapshot has not been created yet
+    Pending,
+    Created,
+}
+
+///A commit or merge in the revision history, together with who created it and when
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct RevisionHistoryEntry {
+    pub hash: HoloHash<holo_hash::hash_type::Action>,
+    pub reference: PerspectiveDiffEntryReference,
+    pub author: AgentPubKey,
+    pub timestamp: DateTime<Utc>,
+}
+
 #[hdk_entry_defs]
 #[unit_enum(UnitEntryTypes)]
 pub enum EntryTypes {
@@ -156,6 +259,26 @@ pub enum EntryTypes {
     Anchor(Anchor),
     #[entry_def(visibility = "private")]
     PrivateOnlineStatus(PerspectiveExpression),
+    #[entry_def(visibility = "private")]
+    LocalPerspectiveCache(LocalPerspectiveCache),
+    #[entry_def(visibility = "private")]
+    LocalPeerSeen(LocalPeerSeen),
+    #[entry_def(visibility = "public")]
+    CompactPerspectiveDiff(CompactPerspectiveDiff),
+    #[entry_def(visibility = "private")]
+    LocalPendingSnapshot(LocalPendingSnapshot),
+    #[entry_def(visibility = "public")]
+    SealedPerspectiveDiff(SealedPerspectiveDiff),
+    #[entry_def(visibility = "public")]
+    EncryptionKey(EncryptionKey),
+    #[entry_def(visibility = "public")]
+    GroupKeyWrap(GroupKeyWrap),
+    #[entry_def(visibility = "public")]
+    Invitation(Invitation),
+    #[entry_def(visibility = "public")]
+    Membership(Membership),
+    #[entry_def(visibility = "public")]
+    BanList(BanList),
 }
 
 #[hdk_link_types]
@@ -166,4 +289,13 @@ pub enum LinkTypes {
     TimePath,
     Index,
     DidLink,
+    EncryptionKey,
+    GroupKey,
+    Membership,
+    BanList,
+}
+
+#[hdk_extern]
+pub fn validate(op: Op) -> ExternResult<ValidateCallbackResult> {
+    validation::validate_op(op)
 }
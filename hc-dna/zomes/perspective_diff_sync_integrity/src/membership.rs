@@ -0,0 +1,149 @@
+use chrono::{DateTime, Utc};
+use hdi::prelude::*;
+
+use crate::signatures::{verify_signed_by, SignatureError};
+use crate::ExpressionProof;
+
+/// What a member of a neighbourhood with an `admin_did` is allowed to do
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Can sync and render the perspective, but not commit to it
+    ReadOnly,
+    Writer,
+    /// Can commit and invite others
+    Admin,
+}
+
+impl Role {
+    pub fn can_write(&self) -> bool {
+        self >= &Role::Writer
+    }
+}
+
+///The part of an Invitation signed by its author
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InvitationData {
+    pub did: String,
+    pub role: Role,
+    ///Memberships accepting the invitation grant its role until then. Since only the latest
+    ///Membership of an agent counts, admins revoke or change a role by letting it expire.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Utc>>,
+}
+
+///Invites a DID into the neighbourhood with the given role, signed by the admin DID or an admin member
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes, PartialEq)]
+pub struct Invitation {
+    pub author: String,
+    pub data: InvitationData,
+    pub timestamp: DateTime<Utc>,
+    pub proof: ExpressionProof,
+    ///The Membership that makes author an admin, None if author is the admin DID of the DNA
+    pub author_membership: Option<ActionHash>,
+}
+
+app_entry!(Invitation);
+
+impl Invitation {
+    /// Whether memberships accepting the invitation grant its role at the given time
+    pub fn holds_at(&self, time: &DateTime<Utc>) -> bool {
+        self.data.expires.iter().all(|expires| time < expires)
+    }
+
+    pub fn verify(&self) -> Result<(), SignatureError> {
+        verify_signed_by(
+            &self.author,
+            &self.data,
+            &self.timestamp,
+            &self.proof.signature,
+        )
+    }
+}
+
+///The part of a Membership signed by its author. agent is the agent key that writes the
+///Membership, so the signature can't be replayed from another agent.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MembershipData {
+    pub agent: String,
+    pub role: Role,
+}
+
+///Accepts an invitation, binding the agent key that writes it to the invited DID
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes, PartialEq)]
+pub struct Membership {
+    pub author: String,
+    pub data: MembershipData,
+    pub timestamp: DateTime<Utc>,
+    pub proof: ExpressionProof,
+    ///The accepted Invitation, None if author is the admin DID of the DNA
+    pub invitation: Option<ActionHash>,
+}
+
+app_entry!(Membership);
+
+impl Membership {
+    pub fn verify(&self) -> Result<(), SignatureError> {
+        verify_signed_by(
+            &self.author,
+            &self.data,
+            &self.timestamp,
+            &self.proof.signature,
+        )
+    }
+}
+
+///An Invitation together with the hash a Membership accepting it has to reference
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes)]
+pub struct ReceivedInvitation {
+    pub hash: ActionHash,
+    pub invitation: Invitation,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::{Invitation, InvitationData, Role};
+    use crate::ExpressionProof;
+
+    #[test]
+    fn roles_are_ordered_by_permissions() {
+        assert!(!Role::ReadOnly.can_write());
+        assert!(Role::Writer.can_write());
+        assert!(Role::Admin.can_write());
+        assert_eq!(
+            serde_json::to_string(&Role::ReadOnly).unwrap(),
+            r#""read_only""#
+        );
+    }
+
+    #[test]
+    fn invitations_hold_until_they_expire() {
+        let now = Utc::now();
+        let mut invitation = Invitation {
+            author: String::from("did:test:admin"),
+            data: InvitationData {
+                did: String::from("did:test:alice"),
+                role: Role::Writer,
+                expires: None,
+            },
+            timestamp: now,
+            proof: ExpressionProof {
+                signature: String::from("sig"),
+                key: String::from("key"),
+            },
+            author_membership: None,
+        };
+        assert!(invitation.holds_at(&(now + Duration::days(365))));
+        //Invitations without expiry are signed just like before expiry existed
+        assert_eq!(
+            serde_json::to_string(&invitation.data).unwrap(),
+            r#"{"did":"did:test:alice","role":"writer"}"#
+        );
+
+        invitation.data.expires = Some(now + Duration::days(1));
+        assert!(invitation.holds_at(&now));
+        assert!(!invitation.holds_at(&(now + Duration::days(1))));
+    }
+}
@@ -0,0 +1,37 @@
+use chrono::{DateTime, Utc};
+use hdi::prelude::*;
+use std::collections::BTreeSet;
+
+use crate::signatures::{verify_signed_by, SignatureError};
+use crate::ExpressionProof;
+
+///The part of a BanList signed by its author
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BanListData {
+    pub banned: BTreeSet<String>,
+}
+
+///The complete set of DIDs banned from timestamp on, replacing every earlier BanList.
+///Bans are lifted by issuing a BanList without the DID.
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes, PartialEq)]
+pub struct BanList {
+    pub author: String,
+    pub data: BanListData,
+    pub timestamp: DateTime<Utc>,
+    pub proof: ExpressionProof,
+    ///The Membership that makes author an admin, None if author is the admin DID of the DNA
+    pub author_membership: Option<ActionHash>,
+}
+
+app_entry!(BanList);
+
+impl BanList {
+    pub fn verify(&self) -> Result<(), SignatureError> {
+        verify_signed_by(
+            &self.author,
+            &self.data,
+            &self.timestamp,
+            &self.proof.signature,
+        )
+    }
+}
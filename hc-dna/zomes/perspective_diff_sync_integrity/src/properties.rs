@@ -0,0 +1,180 @@
+use hdi::prelude::*;
+
+use crate::schema::LinkSchema;
+
+/// How a merge resolves a link that got added on one branch and removed on the other
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MergePolicy {
+    #[default]
+    AddWins,
+    RemoveWins,
+}
+
+/// How new diffs are written to the DHT. Readers understand every encoding regardless of this setting.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffEncoding {
+    /// A plain PerspectiveDiff entry
+    #[default]
+    Plain,
+    /// A CompactPerspectiveDiff, which stores every string of the diff only once
+    Compact,
+    /// A CompactPerspectiveDiff with its payload deflate compressed
+    CompactCompressed,
+}
+
+/// Tuning parameters of a neighbourhood, read from the `properties` block of the DNA manifest.
+/// Every field falls back to its default when missing, so older DNAs keep working unchanged.
+#[derive(Clone, Debug, Serialize, Deserialize, SerializedBytes, PartialEq)]
+#[serde(default)]
+pub struct DnaProperties {
+    /// Maximum number of commits an agent can make within `spam_limit_window_s`, 0 disables the limit
+    pub enforce_spam_limit: usize,
+    pub spam_limit_window_s: i64,
+    /// Accepts links by authors whose DID we can't resolve a key for (did:web, secp256k1
+    /// did:keys, ...) without checking their proof. Anyone can claim such a DID, so this
+    /// should only be enabled for development.
+    pub accept_unresolvable_dids: bool,
+    pub enable_signals: bool,
+    pub snapshot_interval: usize,
+    pub chunk_size: u16,
+    pub merge_policy: MergePolicy,
+    /// Lets every agent merge instead of only the scribe, see `pull::merge`
+    pub deterministic_merges: bool,
+    /// Peers we haven't received a broadcast from for this long are left out of the scribe election
+    pub scribe_liveness_timeout_s: i64,
+    pub diff_encoding: DiffEncoding,
+    /// Seals every diff with a group key only members hold, see `encryption`. Validators can't
+    /// read sealed diffs, so the signatures of their links are not checked. Requires an
+    /// admin_did, which creates the group key and decides who joins.
+    pub encrypted: bool,
+    /// DID of the creator of the neighbourhood. When set, only agents holding a Membership with
+    /// a writing role can commit, see `membership`
+    pub admin_did: Option<String>,
+    /// Shape every committed link has to follow, any link is accepted if None. Validators can't
+    /// check sealed diffs against it, so it can't be combined with `encrypted`.
+    pub link_schema: Option<LinkSchema>,
+}
+
+impl Default for DnaProperties {
+    fn default() -> Self {
+        Self {
+            enforce_spam_limit: 20,
+            spam_limit_window_s: 60,
+            accept_unresolvable_dids: false,
+            enable_signals: true,
+            snapshot_interval: 100,
+            chunk_size: 10000,
+            merge_policy: MergePolicy::default(),
+            deterministic_merges: false,
+            scribe_liveness_timeout_s: 10,
+            diff_encoding: DiffEncoding::default(),
+            encrypted: false,
+            admin_did: None,
+            link_schema: None,
+        }
+    }
+}
+
+impl DnaProperties {
+    /// Loads the properties of the running DNA, using the defaults if none were set.
+    /// Malformed or invalid properties are an error rather than silently replaced by defaults.
+    pub fn load() -> ExternResult<Self> {
+        let properties = dna_info()?.properties;
+        let unset = SerializedBytes::try_from(())
+            .map_err(|error| wasm_error!(WasmErrorInner::Serialize(error)))?;
+        if properties == unset {
+            return Ok(Self::default());
+        }
+        let properties = Self::try_from(properties)
+            .map_err(|error| wasm_error!(WasmErrorInner::Serialize(error)))?;
+        properties.check().map_err(|error| {
+            wasm_error!(WasmErrorInner::Guest(format!(
+                "Invalid DNA properties: {}",
+                error
+            )))
+        })?;
+        Ok(properties)
+    }
+
+    /// Rejects values that can't work, a zero chunk size for example would never fill a chunk
+    pub fn check(&self) -> Result<(), &'static str> {
+        if self.chunk_size == 0 {
+            return Err("chunk_size has to be greater than 0");
+        }
+        if self.snapshot_interval == 0 {
+            return Err("snapshot_interval has to be greater than 0");
+        }
+        if self.encrypted && self.admin_did.is_none() {
+            return Err("encrypted neighbourhoods need an admin_did to decide who gets the key");
+        }
+        if self.encrypted && self.link_schema.is_some() {
+            return Err("link_schema can't be enforced when diffs are encrypted");
+        }
+        Ok(())
+    }
+
+    pub fn scribe_liveness_timeout(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.scribe_liveness_timeout_s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DnaProperties;
+    use crate::schema::LinkSchema;
+
+    #[test]
+    fn missing_properties_use_defaults() {
+        let properties: DnaProperties =
+            serde_json::from_str(r#"{"enable_signals": false, "snapshot_interval": 10}"#).unwrap();
+        assert_eq!(
+            properties,
+            DnaProperties {
+                enable_signals: false,
+                snapshot_interval: 10,
+                ..DnaProperties::default()
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_zero_sizes() {
+        assert_eq!(DnaProperties::default().check(), Ok(()));
+        let properties = DnaProperties {
+            chunk_size: 0,
+            ..DnaProperties::default()
+        };
+        assert!(properties.check().is_err());
+        let properties = DnaProperties {
+            snapshot_interval: 0,
+            ..DnaProperties::default()
+        };
+        assert!(properties.check().is_err());
+    }
+
+    #[test]
+    fn rejects_link_schema_when_encrypted() {
+        let properties = DnaProperties {
+            encrypted: true,
+            admin_did: Some(String::from("did:test:admin")),
+            ..DnaProperties::default()
+        };
+        assert_eq!(properties.check(), Ok(()));
+        let properties = DnaProperties {
+            link_schema: Some(LinkSchema::default()),
+            ..properties
+        };
+        assert!(properties.check().is_err());
+    }
+
+    #[test]
+    fn rejects_encryption_without_admin() {
+        let properties = DnaProperties {
+            encrypted: true,
+            ..DnaProperties::default()
+        };
+        assert!(properties.check().is_err());
+    }
+}
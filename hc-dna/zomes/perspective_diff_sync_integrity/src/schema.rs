@@ -0,0 +1,179 @@
+use hdi::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{LinkExpression, PerspectiveDiff};
+
+/// URI prefixes the source and target of links with a given predicate have to start with.
+/// An empty list allows any value.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct UriPrefixes {
+    pub source: Vec<String>,
+    pub target: Vec<String>,
+}
+
+/// Shape committed links have to follow, set as `link_schema` in the DNA properties
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct LinkSchema {
+    /// Predicates links may use, any predicate if None
+    pub allowed_predicates: Option<BTreeSet<String>>,
+    pub require_predicate: bool,
+    pub require_source: bool,
+    pub require_target: bool,
+    pub prefixes: BTreeMap<String, UriPrefixes>,
+}
+
+/// A link that does not follow the schema, with the first rule it breaks
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SchemaViolation {
+    pub link: LinkExpression,
+    pub reason: String,
+}
+
+fn has_prefix(value: &Option<String>, prefixes: &[String]) -> bool {
+    if prefixes.is_empty() {
+        return true;
+    }
+    match value {
+        Some(value) => prefixes.iter().any(|prefix| value.starts_with(prefix)),
+        None => false,
+    }
+}
+
+impl LinkSchema {
+    pub fn check_link(&self, link: &LinkExpression) -> Result<(), String> {
+        let data = &link.data;
+        if self.require_source && data.source.is_none() {
+            return Err(String::from("Link has no source"));
+        }
+        if self.require_target && data.target.is_none() {
+            return Err(String::from("Link has no target"));
+        }
+        let predicate = match &data.predicate {
+            Some(predicate) => predicate,
+            None if self.require_predicate => return Err(String::from("Link has no predicate")),
+            None => return Ok(()),
+        };
+        if let Some(allowed) = &self.allowed_predicates {
+            if !allowed.contains(predicate) {
+                return Err(format!("Predicate {} is not allowed", predicate));
+            }
+        }
+        if let Some(prefixes) = self.prefixes.get(predicate) {
+            if !has_prefix(&data.source, &prefixes.source) {
+                return Err(format!(
+                    "Source of a {} link must start with one of {:?}",
+                    predicate, prefixes.source
+                ));
+            }
+            if !has_prefix(&data.target, &prefixes.target) {
+                return Err(format!(
+                    "Target of a {} link must start with one of {:?}",
+                    predicate, prefixes.target
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the additions of a diff, removals only need to match links that exist already
+    pub fn check_diff(&self, diff: &PerspectiveDiff) -> Result<(), Vec<SchemaViolation>> {
+        let violations = diff
+            .additions
+            .iter()
+            .filter_map(|link| {
+                self.check_link(link).err().map(|reason| SchemaViolation {
+                    link: link.clone(),
+                    reason,
+                })
+            })
+            .collect::<Vec<SchemaViolation>>();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveDateTime, Utc};
+
+    use super::{LinkSchema, UriPrefixes};
+    use crate::{ExpressionProof, LinkExpression, PerspectiveDiff, Triple};
+
+    fn link(source: Option<&str>, predicate: Option<&str>, target: Option<&str>) -> LinkExpression {
+        LinkExpression {
+            author: String::from("did:test:alice"),
+            data: Triple {
+                source: source.map(String::from),
+                predicate: predicate.map(String::from),
+                target: target.map(String::from),
+            },
+            timestamp: DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(0, 0), Utc),
+            proof: ExpressionProof {
+                signature: String::from("sig"),
+                key: String::from("key"),
+            },
+        }
+    }
+
+    #[test]
+    fn checks_links_against_schema() {
+        let schema: LinkSchema = serde_json::from_str(
+            r#"{
+                "allowed_predicates": ["sioc://has_child", "rdf://name"],
+                "require_source": true,
+                "require_target": true,
+                "prefixes": {"sioc://has_child": {"target": ["expression://"]}}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            schema.prefixes.get("sioc://has_child"),
+            Some(&UriPrefixes {
+                source: vec![],
+                target: vec![String::from("expression://")],
+            })
+        );
+
+        let valid = link(
+            Some("ad4m://self"),
+            Some("sioc://has_child"),
+            Some("expression://1"),
+        );
+        let no_predicate = link(Some("ad4m://self"), None, Some("literal://a"));
+        let empty = link(None, None, None);
+        let unknown_predicate = link(
+            Some("ad4m://self"),
+            Some("rdf://other"),
+            Some("literal://a"),
+        );
+        let wrong_prefix = link(
+            Some("ad4m://self"),
+            Some("sioc://has_child"),
+            Some("literal://a"),
+        );
+        assert_eq!(schema.check_link(&valid), Ok(()));
+        assert_eq!(schema.check_link(&no_predicate), Ok(()));
+        assert!(schema.check_link(&empty).is_err());
+        assert!(schema.check_link(&unknown_predicate).is_err());
+        assert!(schema.check_link(&wrong_prefix).is_err());
+
+        let violations = schema
+            .check_diff(&PerspectiveDiff {
+                additions: vec![valid, empty.clone(), wrong_prefix.clone()],
+                removals: vec![unknown_predicate],
+            })
+            .unwrap_err();
+        assert_eq!(
+            violations
+                .into_iter()
+                .map(|violation| violation.link)
+                .collect::<Vec<_>>(),
+            vec![empty, wrong_prefix]
+        );
+    }
+}
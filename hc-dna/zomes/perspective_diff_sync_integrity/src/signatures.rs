@@ -0,0 +1,336 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+use serde::Serialize;
+use std::convert::TryFrom;
+
+use crate::{LinkExpression, PerspectiveDiff};
+
+const DID_KEY_PREFIX: &str = "did:key:";
+/// Varint encoded multicodec prefix of an Ed25519 public key inside a did:key identifier
+const ED25519_MULTICODEC: [u8; 2] = [0xed, 0x01];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    MalformedDid(String),
+    UnsupportedDid(String),
+    MalformedKey,
+    ForeignKey(String),
+    MalformedSignature,
+    InvalidSignature,
+    Serialization(String),
+}
+
+impl std::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureError::MalformedDid(did) => write!(f, "Malformed did:key identifier: {}", did),
+            SignatureError::UnsupportedDid(did) => {
+                write!(f, "Can't resolve an Ed25519 key for {}", did)
+            }
+            SignatureError::MalformedKey => {
+                write!(f, "did:key does not contain a valid Ed25519 key")
+            }
+            SignatureError::ForeignKey(key) => {
+                write!(f, "Proof key {} does not belong to the author", key)
+            }
+            SignatureError::MalformedSignature => {
+                write!(f, "Signature is not a hex encoded Ed25519 signature")
+            }
+            SignatureError::InvalidSignature => {
+                write!(f, "Signature does not match the signed payload")
+            }
+            SignatureError::Serialization(error) => {
+                write!(f, "Could not serialize signed payload: {}", error)
+            }
+        }
+    }
+}
+
+/// Resolves the Ed25519 public key embedded in a did:key identifier.
+///
+/// Returns None for DIDs we can't resolve from inside the zome (other DID methods or
+/// did:keys with a non Ed25519 key type), since there is nothing we could verify against.
+pub fn resolve_did_key(did: &str) -> Result<Option<PublicKey>, SignatureError> {
+    if !did.starts_with(DID_KEY_PREFIX) {
+        return Ok(None);
+    }
+    //Strip the method prefix and an optional fragment (did:key:z6Mk...#z6Mk...)
+    let identifier = did[DID_KEY_PREFIX.len()..].split('#').next().unwrap_or("");
+    if !identifier.starts_with('z') {
+        return Err(SignatureError::MalformedDid(did.to_string()));
+    }
+    let bytes = bs58::decode(&identifier[1..])
+        .into_vec()
+        .map_err(|_| SignatureError::MalformedDid(did.to_string()))?;
+
+    if !bytes.starts_with(&ED25519_MULTICODEC) {
+        return Ok(None);
+    }
+    PublicKey::from_bytes(&bytes[ED25519_MULTICODEC.len()..])
+        .map(Some)
+        .map_err(|_| SignatureError::MalformedKey)
+}
+
+/// Builds the bytes that get signed for an expression: the JSON object
+/// `{"data": <data>, "timestamp": <ISO 8601 with milliseconds>}` with all object keys sorted,
+/// which is the same string a JS client gets from `JSON.stringify` on a key sorted object.
+pub fn signed_payload<T: Serialize>(
+    data: &T,
+    timestamp: &DateTime<Utc>,
+) -> Result<Vec<u8>, SignatureError> {
+    let data = serde_json::to_value(data)
+        .map_err(|error| SignatureError::Serialization(error.to_string()))?;
+    let payload = serde_json::json!({
+        "data": data,
+        "timestamp": timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+    });
+    serde_json::to_vec(&sort_keys(payload))
+        .map_err(|error| SignatureError::Serialization(error.to_string()))
+}
+
+/// Orders the keys of every object. holochain_serialized_bytes turns on serde_json's
+/// preserve_order, so objects keep the field order of their structs unless sorted here.
+fn sort_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(object) => {
+            let mut entries = object.into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            serde_json::Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sort_keys(value)))
+                    .collect(),
+            )
+        }
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.into_iter().map(sort_keys).collect())
+        }
+        value => value,
+    }
+}
+
+pub fn verify_signature(
+    key: &PublicKey,
+    message: &[u8],
+    signature: &str,
+) -> Result<(), SignatureError> {
+    let signature = hex::decode(signature).map_err(|_| SignatureError::MalformedSignature)?;
+    let signature = Signature::try_from(signature.as_slice())
+        .map_err(|_| SignatureError::MalformedSignature)?;
+    key.verify(message, &signature)
+        .map_err(|_| SignatureError::InvalidSignature)
+}
+
+/// Whether a proof key refers to a key of did, either by a fragment relative to it or by a
+/// DID URL starting with did
+fn is_key_of(did: &str, key: &str) -> bool {
+    key.starts_with('#') || key == did || key.starts_with(&format!("{}#", did))
+}
+
+/// Verifies that the proof of a LinkExpression was made by the key behind its author DID.
+///
+/// Authors we can't resolve a key for are rejected, unless `accept_unresolvable_dids` is set,
+/// in which case their proofs are taken as they are.
+pub fn verify_link_expression(
+    link: &LinkExpression,
+    accept_unresolvable_dids: bool,
+) -> Result<(), SignatureError> {
+    let key = match resolve_did_key(&link.author)? {
+        Some(key) => key,
+        None if accept_unresolvable_dids => return Ok(()),
+        None => return Err(SignatureError::UnsupportedDid(link.author.clone())),
+    };
+    if !is_key_of(&link.author, &link.proof.key) {
+        return Err(SignatureError::ForeignKey(link.proof.key.clone()));
+    }
+    let payload = signed_payload(&link.data, &link.timestamp)?;
+    verify_signature(&key, &payload, &link.proof.signature)
+}
+
+/// Verifies that data was signed by the key behind did. DIDs we can't resolve a key for are
+/// always rejected, since the signature is all that proves who made the claim.
+pub fn verify_signed_by<T: Serialize>(
+    did: &str,
+    data: &T,
+    timestamp: &DateTime<Utc>,
+    signature: &str,
+) -> Result<(), SignatureError> {
+    let key = resolve_did_key(did)?.ok_or(SignatureError::UnsupportedDid(did.to_string()))?;
+    let payload = signed_payload(data, timestamp)?;
+    verify_signature(&key, &payload, signature)
+}
+
+/// Verifies every addition and removal of a diff, returning the first link that fails.
+pub fn verify_perspective_diff(
+    diff: &PerspectiveDiff,
+    accept_unresolvable_dids: bool,
+) -> Result<(), (&LinkExpression, SignatureError)> {
+    for link in diff.additions.iter().chain(diff.removals.iter()) {
+        verify_link_expression(link, accept_unresolvable_dids).map_err(|error| (link, error))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExpressionProof, Triple};
+    use chrono::NaiveDateTime;
+    use ed25519_dalek::{Keypair, SecretKey, Signer};
+
+    // RFC 8032, section 7.1, TEST 1 and TEST 2
+    const TEST_1_SECRET: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+    const TEST_1_PUBLIC: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    const TEST_1_SIGNATURE: &str = "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b";
+    const TEST_2_PUBLIC: &str = "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c";
+    const TEST_2_SIGNATURE: &str = "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00";
+
+    fn public_key(hex_key: &str) -> PublicKey {
+        PublicKey::from_bytes(&hex::decode(hex_key).unwrap()).unwrap()
+    }
+
+    fn did_key(prefix: &[u8], key: &[u8]) -> String {
+        let mut bytes = prefix.to_vec();
+        bytes.extend_from_slice(key);
+        format!("did:key:z{}", bs58::encode(bytes).into_string())
+    }
+
+    fn test_keypair() -> Keypair {
+        let secret = SecretKey::from_bytes(&hex::decode(TEST_1_SECRET).unwrap()).unwrap();
+        let public: PublicKey = (&secret).into();
+        Keypair { secret, public }
+    }
+
+    fn signed_link(keypair: &Keypair, author: String) -> LinkExpression {
+        let data = Triple {
+            source: Some(String::from("ad4m://self")),
+            target: Some(String::from("literal://string:test")),
+            predicate: None,
+        };
+        let timestamp =
+            DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(1_660_000_000, 0), Utc);
+        let payload = signed_payload(&data, &timestamp).unwrap();
+        LinkExpression {
+            author,
+            data,
+            timestamp,
+            proof: ExpressionProof {
+                signature: hex::encode(keypair.sign(&payload).to_bytes()),
+                key: String::from("#key"),
+            },
+        }
+    }
+
+    #[test]
+    fn verifies_rfc8032_test_vectors() {
+        assert_eq!(
+            verify_signature(&public_key(TEST_1_PUBLIC), b"", TEST_1_SIGNATURE),
+            Ok(())
+        );
+        assert_eq!(
+            verify_signature(&public_key(TEST_2_PUBLIC), &[0x72], TEST_2_SIGNATURE),
+            Ok(())
+        );
+        assert_eq!(
+            verify_signature(&public_key(TEST_2_PUBLIC), &[0x73], TEST_2_SIGNATURE),
+            Err(SignatureError::InvalidSignature)
+        );
+        assert_eq!(
+            verify_signature(&public_key(TEST_1_PUBLIC), b"", "not hex"),
+            Err(SignatureError::MalformedSignature)
+        );
+    }
+
+    #[test]
+    fn resolves_did_keys() {
+        let key_bytes = hex::decode(TEST_1_PUBLIC).unwrap();
+        let did = did_key(&ED25519_MULTICODEC, &key_bytes);
+        assert!(did.starts_with("did:key:z6Mk"));
+
+        let resolved = resolve_did_key(&did).unwrap().unwrap();
+        assert_eq!(resolved.as_bytes().to_vec(), key_bytes);
+
+        let with_fragment = format!("{}#{}", did, &did[DID_KEY_PREFIX.len()..]);
+        assert!(resolve_did_key(&with_fragment).unwrap().is_some());
+
+        //secp256k1 did:keys and other did methods can't be resolved here
+        let secp256k1 = did_key(&[0xe7, 0x01], &[2; 33]);
+        assert!(resolve_did_key(&secp256k1).unwrap().is_none());
+        assert!(resolve_did_key("did:test:alice").unwrap().is_none());
+
+        assert!(resolve_did_key("did:key:6Mk").is_err());
+    }
+
+    #[test]
+    fn builds_canonical_payload() {
+        let data = Triple {
+            source: Some(String::from("a")),
+            target: Some(String::from("b")),
+            predicate: None,
+        };
+        let timestamp = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(0, 0), Utc);
+        let payload = signed_payload(&data, &timestamp).unwrap();
+        assert_eq!(
+            String::from_utf8(payload).unwrap(),
+            r#"{"data":{"predicate":null,"source":"a","target":"b"},"timestamp":"1970-01-01T00:00:00.000Z"}"#
+        );
+    }
+
+    #[test]
+    fn verifies_link_expressions() {
+        let keypair = test_keypair();
+        let author = did_key(&ED25519_MULTICODEC, keypair.public.as_bytes());
+        let link = signed_link(&keypair, author.clone());
+        assert_eq!(verify_link_expression(&link, false), Ok(()));
+
+        let mut forged = link.clone();
+        forged.data.target = Some(String::from("literal://string:forged"));
+        assert_eq!(
+            verify_link_expression(&forged, false),
+            Err(SignatureError::InvalidSignature)
+        );
+
+        let mut other_author = link.clone();
+        other_author.author = did_key(&ED25519_MULTICODEC, &hex::decode(TEST_2_PUBLIC).unwrap());
+        assert_eq!(
+            verify_link_expression(&other_author, false),
+            Err(SignatureError::InvalidSignature)
+        );
+
+        let mut own_key = link.clone();
+        own_key.proof.key = format!("{}#{}", author, &author[DID_KEY_PREFIX.len()..]);
+        assert_eq!(verify_link_expression(&own_key, false), Ok(()));
+
+        let mut foreign_key = link.clone();
+        foreign_key.proof.key = String::from("did:key:z6MkOther#z6MkOther");
+        assert_eq!(
+            verify_link_expression(&foreign_key, false),
+            Err(SignatureError::ForeignKey(foreign_key.proof.key.clone()))
+        );
+
+        let diff = PerspectiveDiff {
+            additions: vec![link],
+            removals: vec![forged.clone()],
+        };
+        assert_eq!(
+            verify_perspective_diff(&diff, false),
+            Err((&forged, SignatureError::InvalidSignature))
+        );
+    }
+
+    #[test]
+    fn rejects_unresolvable_authors_unless_accepted() {
+        let keypair = test_keypair();
+        for author in [
+            String::from("did:foo:mallory"),
+            did_key(&[0xe7, 0x01], &[2; 33]),
+        ] {
+            let link = signed_link(&keypair, author.clone());
+            assert_eq!(
+                verify_link_expression(&link, false),
+                Err(SignatureError::UnsupportedDid(author))
+            );
+            assert_eq!(verify_link_expression(&link, true), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,804 @@
+use chrono::{DateTime, TimeZone, Utc};
+use hdi::prelude::*;
+use std::collections::BTreeSet;
+
+use crate::compact::{decode_diff, CompactPerspectiveDiff};
+use crate::encryption::{group_key_ref, EncryptionKey, GroupKeyWrap, SealedPerspectiveDiff};
+use crate::membership::{Invitation, Membership, Role};
+use crate::moderation::BanList;
+use crate::properties::DnaProperties;
+use crate::signatures::verify_perspective_diff;
+use crate::{
+    Anchor, EntryTypes, HashReference, LinkTypes, PerspectiveDiff, PerspectiveDiffEntryReference,
+    Snapshot, UnitEntryTypes,
+};
+
+pub fn validate_op(op: Op) -> ExternResult<ValidateCallbackResult> {
+    match op.to_type::<EntryTypes, LinkTypes>()? {
+        OpType::StoreEntry(OpEntry::CreateEntry { app_entry, action }) => {
+            if let Some(reason) =
+                check_entry_encryption(app_entry.to_unit(), DnaProperties::load()?.encrypted)
+            {
+                return invalid(reason);
+            }
+            if writes_perspective(&app_entry) {
+                if let ValidateCallbackResult::Invalid(reason) = validate_writer(&action)? {
+                    return Ok(ValidateCallbackResult::Invalid(reason));
+                }
+            }
+            match app_entry {
+                EntryTypes::PerspectiveDiff(diff) => validate_perspective_diff(diff),
+                EntryTypes::CompactPerspectiveDiff(compact) => validate_compact_diff(compact),
+                EntryTypes::PerspectiveDiffEntryReference(reference) => {
+                    validate_diff_entry_reference(reference)
+                }
+                EntryTypes::Snapshot(snapshot) => validate_snapshot(snapshot),
+                EntryTypes::HashReference(reference) => validate_hash_reference(reference),
+                EntryTypes::Invitation(invitation) => validate_invitation(invitation),
+                EntryTypes::Membership(membership) => validate_membership(membership, &action),
+                EntryTypes::BanList(ban_list) => validate_ban_list(ban_list),
+                _ => Ok(ValidateCallbackResult::Valid),
+            }
+        }
+        OpType::RegisterCreateLink {
+            base_address,
+            target_address,
+            tag,
+            link_type,
+            action,
+        } => match link_type {
+            _ if !DnaProperties::load()?.encrypted && encryption_link(&link_type) => {
+                invalid("Links to encryption keys require an encrypted neighbourhood")
+            }
+            LinkTypes::Snapshot => validate_snapshot_link(base_address, target_address),
+            LinkTypes::DidLink => validate_did_link(base_address, target_address, action.author),
+            LinkTypes::EncryptionKey => {
+                validate_encryption_key_link(base_address, target_address, action.author)
+            }
+            LinkTypes::GroupKey => validate_group_key_link(target_address, &action),
+            LinkTypes::Membership => validate_membership_link(target_address, tag),
+            LinkTypes::BanList => validate_ban_list_link(target_address),
+            LinkTypes::Index => {
+                validate_index_link(base_address, target_address, tag, action.author)
+            }
+            _ => Ok(ValidateCallbackResult::Valid),
+        },
+        OpType::RegisterDeleteLink {
+            original_action,
+            link_type,
+            action,
+            ..
+        } => validate_delete_link(link_type, &original_action.author, &action.author),
+        //Diffs, references and everything moderation relies on make up the shared history, so no
+        //entry is ever changed once written. Newer entries supersede older ones instead.
+        OpType::StoreEntry(OpEntry::UpdateEntry { .. })
+        | OpType::RegisterUpdate(OpUpdate::Entry { .. })
+        | OpType::RegisterUpdate(OpUpdate::PrivateEntry { .. }) => {
+            invalid("Entries of this DNA are immutable and can't be updated")
+        }
+        OpType::RegisterDelete(OpDelete::Entry { .. })
+        | OpType::RegisterDelete(OpDelete::PrivateEntry { .. }) => {
+            invalid("Entries of this DNA are immutable and can't be deleted")
+        }
+        OpType::RegisterAgentActivity(OpActivity::CreateEntry {
+            action,
+            app_entry_type,
+        }) => match app_entry_type {
+            Some(UnitEntryTypes::PerspectiveDiff)
+            | Some(UnitEntryTypes::CompactPerspectiveDiff)
+            | Some(UnitEntryTypes::SealedPerspectiveDiff) => validate_commit_rate(action, false),
+            Some(UnitEntryTypes::PerspectiveDiffEntryReference) => {
+                validate_commit_rate(action, true)
+            }
+            _ => Ok(ValidateCallbackResult::Valid),
+        },
+        _ => Ok(ValidateCallbackResult::Valid),
+    }
+}
+
+fn invalid(reason: &str) -> ExternResult<ValidateCallbackResult> {
+    Ok(ValidateCallbackResult::Invalid(String::from(reason)))
+}
+
+/// Fetches the record behind an action hash and tries to read its app entry as T.
+/// Returns None if the record has no app entry or it does not deserialize as T.
+fn must_get_app_entry<T>(hash: ActionHash) -> ExternResult<Option<T>>
+where
+    T: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+{
+    let record = must_get_valid_record(hash)?;
+    Ok(record.entry().to_app_option::<T>().ok().flatten())
+}
+
+fn must_get_app_entry_by_entry_hash<T>(hash: EntryHash) -> ExternResult<Option<T>>
+where
+    T: TryFrom<SerializedBytes, Error = SerializedBytesError>,
+{
+    match must_get_entry(hash)?.as_content() {
+        Entry::App(bytes) => Ok(T::try_from(bytes.0.clone()).ok()),
+        _ => Ok(None),
+    }
+}
+
+/// Checks that the record is a diff in an encoding this neighbourhood accepts: sealed if it is
+/// encrypted, plain or compact otherwise.
+/// Sealed diffs can only be read by members, so their link signatures are not checked: an
+/// encrypted neighbourhood trusts its members with them. The properties refuse a link schema
+/// for encrypted neighbourhoods, since it could not be enforced either.
+fn is_diff_entry(hash: ActionHash) -> ExternResult<bool> {
+    let properties = DnaProperties::load()?;
+    let record = must_get_valid_record(hash)?;
+    match record.entry().as_option() {
+        Some(Entry::App(bytes)) if properties.encrypted => {
+            Ok(SealedPerspectiveDiff::try_from(bytes.clone().into_sb()).is_ok())
+        }
+        Some(Entry::App(bytes)) => {
+            Ok(decode_diff(bytes.clone().into_sb(), properties.chunk_size).is_ok())
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Encrypted neighbourhoods only accept sealed diffs, so no member leaks the graph by accident.
+/// Sealed diffs and the keys to read them are refused everywhere else, since no validator could
+/// check the links in them and peers without a group key could not read them.
+fn check_entry_encryption(entry_type: UnitEntryTypes, encrypted: bool) -> Option<&'static str> {
+    match entry_type {
+        UnitEntryTypes::PerspectiveDiff | UnitEntryTypes::CompactPerspectiveDiff if encrypted => {
+            Some("Diffs must be sealed in an encrypted neighbourhood")
+        }
+        UnitEntryTypes::SealedPerspectiveDiff
+        | UnitEntryTypes::EncryptionKey
+        | UnitEntryTypes::GroupKeyWrap
+            if !encrypted =>
+        {
+            Some("Sealed diffs and encryption keys require an encrypted neighbourhood")
+        }
+        _ => None,
+    }
+}
+
+fn encryption_link(link_type: &LinkTypes) -> bool {
+    matches!(link_type, LinkTypes::EncryptionKey | LinkTypes::GroupKey)
+}
+
+fn validate_compact_diff(compact: CompactPerspectiveDiff) -> ExternResult<ValidateCallbackResult> {
+    match compact.decode(DnaProperties::load()?.chunk_size) {
+        Ok(diff) => validate_perspective_diff(diff),
+        Err(error) => Ok(ValidateCallbackResult::Invalid(format!(
+            "CompactPerspectiveDiff can not be decoded: {}",
+            error
+        ))),
+    }
+}
+
+fn validate_hash_reference(reference: HashReference) -> ExternResult<ValidateCallbackResult> {
+    if must_get_app_entry::<PerspectiveDiffEntryReference>(reference.hash)?.is_none() {
+        return invalid("HashReference.hash must point to a PerspectiveDiffEntryReference");
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+fn validate_perspective_diff(diff: PerspectiveDiff) -> ExternResult<ValidateCallbackResult> {
+    let properties = DnaProperties::load()?;
+    if let Some(schema) = &properties.link_schema {
+        if let Err(violations) = schema.check_diff(&diff) {
+            return Ok(ValidateCallbackResult::Invalid(format!(
+                "PerspectiveDiff contains {} links that violate the link schema, the first: {}",
+                violations.len(),
+                violations[0].reason
+            )));
+        }
+    }
+    match verify_perspective_diff(&diff, properties.accept_unresolvable_dids) {
+        Ok(()) => Ok(ValidateCallbackResult::Valid),
+        Err((link, error)) => Ok(ValidateCallbackResult::Invalid(format!(
+            "PerspectiveDiff contains a link by {} with an invalid proof: {}",
+            link.author, error
+        ))),
+    }
+}
+
+fn validate_diff_entry_reference(
+    reference: PerspectiveDiffEntryReference,
+) -> ExternResult<ValidateCallbackResult> {
+    if !is_diff_entry(reference.diff.clone())? {
+        return invalid("PerspectiveDiffEntryReference.diff does not point to a PerspectiveDiff");
+    }
+    if let Some(chunks) = &reference.diff_chunks {
+        if chunks.first() != Some(&reference.diff) {
+            return invalid(
+                "PerspectiveDiffEntryReference.diff must be the first of its diff_chunks",
+            );
+        }
+        for chunk in chunks.iter().skip(1) {
+            if !is_diff_entry(chunk.clone())? {
+                return invalid(
+                    "PerspectiveDiffEntryReference.diff_chunks must point to PerspectiveDiffs",
+                );
+            }
+        }
+    }
+
+    let mut max_diffs_since_snapshot = 1;
+    if let Some(parents) = reference.parents {
+        if let Some(reason) = check_parents(&parents) {
+            return invalid(reason);
+        }
+        for parent in parents {
+            //Parents have to exist before the child is created, which also rules out cycles
+            //since an action can never reference its own (or a later) action hash
+            match must_get_app_entry::<PerspectiveDiffEntryReference>(parent)? {
+                Some(parent) => max_diffs_since_snapshot += parent.diffs_since_snapshot,
+                None => {
+                    return invalid(
+                        "PerspectiveDiffEntryReference.parents must point to PerspectiveDiffEntryReferences",
+                    )
+                }
+            }
+        }
+    }
+
+    //A commit increments the counter of its parent by one, a merge sums up its parents and adds one
+    if reference.diffs_since_snapshot > max_diffs_since_snapshot {
+        return invalid(
+            "PerspectiveDiffEntryReference.diffs_since_snapshot is larger than its parents allow",
+        );
+    }
+
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Checks the parents of a reference for problems that show without fetching them
+fn check_parents(parents: &[ActionHash]) -> Option<&'static str> {
+    if parents.is_empty() {
+        return Some("PerspectiveDiffEntryReference.parents must be None instead of empty");
+    }
+    let unique_parents = parents.iter().collect::<BTreeSet<_>>();
+    if unique_parents.len() != parents.len() {
+        return Some("PerspectiveDiffEntryReference.parents contains duplicates");
+    }
+    None
+}
+
+fn validate_snapshot(snapshot: Snapshot) -> ExternResult<ValidateCallbackResult> {
+    for chunk in snapshot.diff_chunks {
+        if !is_diff_entry(chunk)? {
+            return invalid("Snapshot.diff_chunks must point to PerspectiveDiffs");
+        }
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+fn validate_snapshot_link(
+    base: AnyLinkableHash,
+    target: AnyLinkableHash,
+) -> ExternResult<ValidateCallbackResult> {
+    let base = match base.into_entry_hash() {
+        Some(base) => base,
+        None => return invalid("Snapshot link base must be an entry hash"),
+    };
+    let target = match target.into_entry_hash() {
+        Some(target) => target,
+        None => return invalid("Snapshot link target must be an entry hash"),
+    };
+    if must_get_app_entry_by_entry_hash::<PerspectiveDiffEntryReference>(base)?.is_none() {
+        return invalid("Snapshot link base must be a PerspectiveDiffEntryReference");
+    }
+    if must_get_app_entry_by_entry_hash::<Snapshot>(target)?.is_none() {
+        return invalid("Snapshot link target must be a Snapshot");
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Links can only be deleted by their author. Links that make up the shared history or the
+/// moderation state of the neighbourhood can't be deleted at all, otherwise deleting a BanList
+/// or Membership link would undo a decision of the admin.
+fn validate_delete_link(
+    link_type: LinkTypes,
+    original_author: &AgentPubKey,
+    author: &AgentPubKey,
+) -> ExternResult<ValidateCallbackResult> {
+    match link_type {
+        LinkTypes::Snapshot
+        | LinkTypes::HashRef
+        | LinkTypes::TimePath
+        | LinkTypes::GroupKey
+        | LinkTypes::Membership
+        | LinkTypes::BanList => invalid("Links of this type can't be deleted"),
+        _ if original_author != author => invalid("Only the author of a link can delete it"),
+        _ => Ok(ValidateCallbackResult::Valid),
+    }
+}
+
+fn validate_did_link(
+    base: AnyLinkableHash,
+    target: AnyLinkableHash,
+    author: AgentPubKey,
+) -> ExternResult<ValidateCallbackResult> {
+    //DidLinks are created in both directions between the authors agent key and their DID anchor
+    let author = AnyLinkableHash::from(author);
+    let did_anchor = if base == author {
+        target
+    } else if target == author {
+        base
+    } else {
+        return invalid("DidLink must connect the authors own agent key");
+    };
+    let did_anchor = match did_anchor.into_entry_hash() {
+        Some(did_anchor) => did_anchor,
+        None => return invalid("DidLink must connect to a DID anchor entry"),
+    };
+    if must_get_app_entry_by_entry_hash::<Anchor>(did_anchor)?.is_none() {
+        return invalid("DidLink must connect to a DID anchor entry");
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+fn validate_encryption_key_link(
+    base: AnyLinkableHash,
+    target: AnyLinkableHash,
+    author: AgentPubKey,
+) -> ExternResult<ValidateCallbackResult> {
+    if base != AnyLinkableHash::from(author) {
+        return invalid("Agents can only publish their own encryption key");
+    }
+    let target = match target.into_entry_hash() {
+        Some(target) => target,
+        None => return invalid("EncryptionKey link target must be an EncryptionKey entry"),
+    };
+    if must_get_app_entry_by_entry_hash::<EncryptionKey>(target)?.is_none() {
+        return invalid("EncryptionKey link target must be an EncryptionKey entry");
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+fn validate_group_key_link(
+    target: AnyLinkableHash,
+    action: &CreateLink,
+) -> ExternResult<ValidateCallbackResult> {
+    let wrap = match target.into_entry_hash() {
+        Some(target) => must_get_app_entry_by_entry_hash::<GroupKeyWrap>(target)?,
+        None => None,
+    };
+    let wrap = match wrap {
+        Some(wrap) => wrap,
+        None => return invalid("GroupKey link target must be a GroupKeyWrap entry"),
+    };
+    if wrap.key_ref != group_key_ref()? {
+        return invalid("GroupKeyWrap.key_ref must be the group key of this neighbourhood");
+    }
+    //Without an admin_did every agent is a member
+    if DnaProperties::load()?.admin_did.is_some()
+        && member_role(&action.author, &action.prev_action, &action.timestamp)?.is_none()
+    {
+        return invalid("Only members can share the group key");
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+fn validate_membership_link(
+    target: AnyLinkableHash,
+    tag: LinkTag,
+) -> ExternResult<ValidateCallbackResult> {
+    let target = match target.into_action_hash() {
+        Some(target) => target,
+        None => return invalid("Membership link target must be an Invitation or Membership"),
+    };
+    let found = if tag == LinkTag::new("invitation") {
+        must_get_app_entry::<Invitation>(target)?.is_some()
+    } else {
+        must_get_app_entry::<Membership>(target)?.is_some()
+    };
+    if !found {
+        return invalid("Membership link target must be an Invitation or Membership");
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+fn validate_invitation(invitation: Invitation) -> ExternResult<ValidateCallbackResult> {
+    let admin_did = match DnaProperties::load()?.admin_did {
+        Some(admin_did) => admin_did,
+        None => return invalid("Invitations require an admin_did in the DNA properties"),
+    };
+    if let Err(error) = invitation.verify() {
+        return Ok(ValidateCallbackResult::Invalid(format!(
+            "Invitation has an invalid proof: {}",
+            error
+        )));
+    }
+    if !is_admin(
+        &invitation.author,
+        invitation.author_membership,
+        &admin_did,
+        &invitation.timestamp,
+    )? {
+        return invalid("Invitation.author must be the admin DID or hold an admin Membership");
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Whether did is the admin DID of the DNA, or author_membership is an admin Membership of did
+/// that still holds at the given time
+fn is_admin(
+    did: &str,
+    author_membership: Option<ActionHash>,
+    admin_did: &str,
+    time: &DateTime<Utc>,
+) -> ExternResult<bool> {
+    if did == admin_did {
+        return Ok(true);
+    }
+    let membership = match author_membership {
+        Some(membership) => must_get_app_entry::<Membership>(membership)?,
+        None => None,
+    };
+    match membership {
+        Some(membership) if membership.author == did && membership.data.role == Role::Admin => {
+            membership_holds_at(&membership, time)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Whether the Invitation a Membership accepted still holds at the given time
+fn membership_holds_at(membership: &Membership, time: &DateTime<Utc>) -> ExternResult<bool> {
+    match &membership.invitation {
+        Some(invitation) => Ok(must_get_app_entry::<Invitation>(invitation.clone())?
+            .iter()
+            .any(|invitation| invitation.holds_at(time))),
+        //Only the admin DID joins without an invitation, and it never expires
+        None => Ok(true),
+    }
+}
+
+fn action_time(timestamp: &Timestamp) -> DateTime<Utc> {
+    Utc.timestamp_nanos(timestamp.0 * 1000)
+}
+
+fn validate_ban_list(ban_list: BanList) -> ExternResult<ValidateCallbackResult> {
+    let admin_did = match DnaProperties::load()?.admin_did {
+        Some(admin_did) => admin_did,
+        None => return invalid("Ban lists require an admin_did in the DNA properties"),
+    };
+    if let Err(error) = ban_list.verify() {
+        return Ok(ValidateCallbackResult::Invalid(format!(
+            "BanList has an invalid proof: {}",
+            error
+        )));
+    }
+    if !is_admin(
+        &ban_list.author,
+        ban_list.author_membership,
+        &admin_did,
+        &ban_list.timestamp,
+    )? {
+        return invalid("BanList.author must be the admin DID or hold an admin Membership");
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+fn validate_ban_list_link(target: AnyLinkableHash) -> ExternResult<ValidateCallbackResult> {
+    let target = match target.into_action_hash() {
+        Some(target) => target,
+        None => return invalid("BanList link target must be a BanList"),
+    };
+    if must_get_app_entry::<BanList>(target)?.is_none() {
+        return invalid("BanList link target must be a BanList");
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+fn validate_membership(
+    membership: Membership,
+    action: &Create,
+) -> ExternResult<ValidateCallbackResult> {
+    let admin_did = match DnaProperties::load()?.admin_did {
+        Some(admin_did) => admin_did,
+        None => return invalid("Memberships require an admin_did in the DNA properties"),
+    };
+    if membership.data.agent != action.author.to_string() {
+        return invalid("Membership.data.agent must be the agent writing it");
+    }
+    if let Err(error) = membership.verify() {
+        return Ok(ValidateCallbackResult::Invalid(format!(
+            "Membership has an invalid proof: {}",
+            error
+        )));
+    }
+    //The admin DID needs no invitation to join
+    if membership.author == admin_did {
+        if membership.data.role != Role::Admin {
+            return invalid("The admin DID can only hold the admin role");
+        }
+        return Ok(ValidateCallbackResult::Valid);
+    }
+    let invitation = match membership.invitation {
+        Some(invitation) => must_get_app_entry::<Invitation>(invitation)?,
+        None => None,
+    };
+    match invitation {
+        Some(invitation) if !invitation.holds_at(&action_time(&action.timestamp)) => {
+            invalid("Membership accepts an expired Invitation")
+        }
+        Some(invitation)
+            if invitation.data.did == membership.author
+                && invitation.data.role == membership.data.role =>
+        {
+            Ok(ValidateCallbackResult::Valid)
+        }
+        Some(_) => invalid("Membership must match the DID and role of its Invitation"),
+        None => invalid("Membership.invitation must point to an Invitation"),
+    }
+}
+
+fn writes_perspective(entry: &EntryTypes) -> bool {
+    matches!(
+        entry,
+        EntryTypes::PerspectiveDiff(_)
+            | EntryTypes::CompactPerspectiveDiff(_)
+            | EntryTypes::SealedPerspectiveDiff(_)
+            | EntryTypes::PerspectiveDiffEntryReference(_)
+    )
+}
+
+/// Rejects diffs and diff references of agents whose latest Membership before this action does
+/// not grant a writing role. Neighbourhoods without an admin_did accept writes from everyone.
+fn validate_writer(action: &Create) -> ExternResult<ValidateCallbackResult> {
+    if DnaProperties::load()?.admin_did.is_none() {
+        return Ok(ValidateCallbackResult::Valid);
+    }
+    match member_role(&action.author, &action.prev_action, &action.timestamp)? {
+        Some(role) if role.can_write() => Ok(ValidateCallbackResult::Valid),
+        _ => invalid("Only members with the writer or admin role can write to this neighbourhood"),
+    }
+}
+
+/// Role of the latest Membership author wrote up to prev_action, None if author is no member or
+/// the invitation of that Membership expired by the given time
+fn member_role(
+    author: &AgentPubKey,
+    prev_action: &ActionHash,
+    timestamp: &Timestamp,
+) -> ExternResult<Option<Role>> {
+    let membership_type: EntryType = UnitEntryTypes::Membership.try_into()?;
+    let activity = must_get_agent_activity(author.clone(), ChainFilter::new(prev_action.clone()))?;
+    let latest_membership = activity
+        .iter()
+        .filter(|activity| activity.action.action().entry_type() == Some(&membership_type))
+        .max_by_key(|activity| activity.action.action().action_seq());
+    let membership = match latest_membership {
+        Some(activity) => {
+            must_get_app_entry::<Membership>(activity.action.action_address().clone())?
+        }
+        None => None,
+    };
+    match membership {
+        Some(membership) if membership_holds_at(&membership, &action_time(timestamp))? => {
+            Ok(Some(membership.data.role))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn validate_index_link(
+    base: AnyLinkableHash,
+    target: AnyLinkableHash,
+    tag: LinkTag,
+    author: AgentPubKey,
+) -> ExternResult<ValidateCallbackResult> {
+    let base = match base.into_entry_hash() {
+        Some(base) => base,
+        None => return invalid("Index link base must be an anchor entry"),
+    };
+    if must_get_app_entry_by_entry_hash::<Anchor>(base)?.is_none() {
+        return invalid("Index link base must be an anchor entry");
+    }
+
+    if tag == LinkTag::new("active_agent") {
+        if target != AnyLinkableHash::from(author) {
+            return invalid("Agents can only register themselves as active agents");
+        }
+    } else {
+        let target = match target.into_action_hash() {
+            Some(target) => target,
+            None => return invalid("Index link target must be a PerspectiveDiffEntryReference"),
+        };
+        if must_get_app_entry::<PerspectiveDiffEntryReference>(target)?.is_none() {
+            return invalid("Index link target must be a PerspectiveDiffEntryReference");
+        }
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Rejects diffs and diff references of agents that committed more than `enforce_spam_limit`
+/// times within the `spam_limit_window_s` before this action, judged by their own source chain.
+fn validate_commit_rate(
+    action: Create,
+    is_reference: bool,
+) -> ExternResult<ValidateCallbackResult> {
+    let properties = DnaProperties::load()?;
+    if properties.enforce_spam_limit == 0 {
+        return Ok(ValidateCallbackResult::Valid);
+    }
+    let window_start = action.timestamp.0 - properties.spam_limit_window_s * 1_000_000;
+    let reference_type: EntryType = UnitEntryTypes::PerspectiveDiffEntryReference.try_into()?;
+
+    let activity = must_get_agent_activity(
+        action.author.clone(),
+        ChainFilter::new(action.prev_action.clone()),
+    )?;
+    let commits = count_commits(
+        activity.iter().map(|activity| activity.action.action()),
+        window_start,
+        &reference_type,
+    );
+
+    //Snapshot chunks are diffs as well, which sync writes after the commits they cover;
+    //so a diff may see one more reference than the limit allows
+    let exceeded = if is_reference {
+        commits >= properties.enforce_spam_limit
+    } else {
+        commits > properties.enforce_spam_limit
+    };
+    if exceeded {
+        return Ok(ValidateCallbackResult::Invalid(format!(
+            "Agent exceeded the limit of {} commits within {} seconds",
+            properties.enforce_spam_limit, properties.spam_limit_window_s
+        )));
+    }
+    Ok(ValidateCallbackResult::Valid)
+}
+
+/// Counts the diff references created from window_start on. The reference entry type is public,
+/// so private entries of the same zome never count.
+fn count_commits<'a>(
+    actions: impl Iterator<Item = &'a Action>,
+    window_start: i64,
+    reference_type: &EntryType,
+) -> usize {
+    actions
+        .filter(|previous| match previous {
+            Action::Create(create) => {
+                &create.entry_type == reference_type && create.timestamp.0 >= window_start
+            }
+            _ => false,
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use hdi::prelude::*;
+
+    use super::{
+        check_entry_encryption, check_parents, count_commits, encryption_link,
+        validate_delete_link, writes_perspective,
+    };
+    use crate::{Anchor, EntryTypes, LinkTypes, PerspectiveDiff, UnitEntryTypes};
+
+    fn is_valid(result: ExternResult<ValidateCallbackResult>) -> bool {
+        matches!(result, Ok(ValidateCallbackResult::Valid))
+    }
+
+    #[test]
+    fn only_authors_delete_their_links() {
+        let alice = AgentPubKey::from_raw_36(vec![0xa1; 36]);
+        let mallory = AgentPubKey::from_raw_36(vec![0xa2; 36]);
+
+        assert!(is_valid(validate_delete_link(
+            LinkTypes::ActiveAgent,
+            &alice,
+            &alice
+        )));
+        assert!(!is_valid(validate_delete_link(
+            LinkTypes::ActiveAgent,
+            &alice,
+            &mallory
+        )));
+        //Not even the author can undo moderation decisions or rewrite history
+        for link_type in [
+            LinkTypes::BanList,
+            LinkTypes::Membership,
+            LinkTypes::Snapshot,
+            LinkTypes::GroupKey,
+        ] {
+            assert!(!is_valid(validate_delete_link(link_type, &alice, &alice)));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_parents() {
+        let first = ActionHash::from_raw_36(vec![0xdb; 36]);
+        let second = ActionHash::from_raw_36(vec![0xdc; 36]);
+        assert_eq!(check_parents(&[first.clone(), second]), None);
+        assert!(check_parents(&[]).is_some());
+        assert!(check_parents(&[first.clone(), first]).is_some());
+    }
+
+    #[test]
+    fn only_diffs_and_references_write_the_perspective() {
+        assert!(writes_perspective(&EntryTypes::PerspectiveDiff(
+            PerspectiveDiff::new()
+        )));
+        assert!(!writes_perspective(&EntryTypes::Anchor(Anchor(
+            String::from("members")
+        ))));
+    }
+
+    #[test]
+    fn only_encrypted_neighbourhoods_accept_sealed_diffs_and_keys() {
+        for entry_type in [
+            UnitEntryTypes::SealedPerspectiveDiff,
+            UnitEntryTypes::EncryptionKey,
+            UnitEntryTypes::GroupKeyWrap,
+        ] {
+            assert!(check_entry_encryption(entry_type, false).is_some());
+            assert_eq!(check_entry_encryption(entry_type, true), None);
+        }
+        assert!(encryption_link(&LinkTypes::EncryptionKey));
+        assert!(encryption_link(&LinkTypes::GroupKey));
+        assert!(!encryption_link(&LinkTypes::Snapshot));
+    }
+
+    #[test]
+    fn encrypted_neighbourhoods_refuse_plain_and_compact_diffs() {
+        for entry_type in [
+            UnitEntryTypes::PerspectiveDiff,
+            UnitEntryTypes::CompactPerspectiveDiff,
+        ] {
+            assert!(check_entry_encryption(entry_type, true).is_some());
+            assert_eq!(check_entry_encryption(entry_type, false), None);
+        }
+        assert_eq!(
+            check_entry_encryption(UnitEntryTypes::PerspectiveDiffEntryReference, true),
+            None
+        );
+    }
+
+    #[test]
+    fn counts_only_reference_creates_within_the_window() {
+        let reference_type = EntryType::App(AppEntryDef::new(
+            EntryDefIndex(0),
+            ZomeIndex(0),
+            EntryVisibility::Public,
+        ));
+        let create = |timestamp: i64, entry_type: EntryType| {
+            Action::Create(Create {
+                author: AgentPubKey::from_raw_36(vec![0xa1; 36]),
+                timestamp: Timestamp(timestamp),
+                action_seq: 4,
+                prev_action: ActionHash::from_raw_36(vec![0xdb; 36]),
+                entry_type,
+                entry_hash: EntryHash::from_raw_36(vec![0xdb; 36]),
+                weight: EntryRateWeight::default(),
+            })
+        };
+        let actions = [
+            create(10, reference_type.clone()),
+            create(20, reference_type.clone()),
+            //Before the window
+            create(5, reference_type.clone()),
+            //Diffs, chunks and private bookkeeping entries
+            create(
+                20,
+                EntryType::App(AppEntryDef::new(
+                    EntryDefIndex(1),
+                    ZomeIndex(0),
+                    EntryVisibility::Public,
+                )),
+            ),
+            create(
+                20,
+                EntryType::App(AppEntryDef::new(
+                    EntryDefIndex(0),
+                    ZomeIndex(0),
+                    EntryVisibility::Private,
+                )),
+            ),
+            create(20, EntryType::AgentPubKey),
+        ];
+        assert_eq!(count_commits(actions.iter(), 10, &reference_type), 2);
+        assert_eq!(count_commits(actions.iter(), 0, &reference_type), 3);
+    }
+}
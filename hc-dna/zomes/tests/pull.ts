@@ -36,7 +36,7 @@ import test from "tape-promise/tape.js";
 //     const pull_bob = await bobHapps.cells[0].callZome({
 //         zome_name: "perspective_diff_sync", 
 //         fn_name: "pull",
-//         payload: { hash: commit, is_scribe: false } 
+//         payload: { hash: commit } 
 //     });
 //     //@ts-ignore
 //     t.equal(pull_bob.diff.additions.length, 1);
@@ -195,7 +195,7 @@ export async function mergeFetch(t) {
         await bobHapps.cells[0].callZome({
             zome_name: "perspective_diff_sync", 
             fn_name: "pull",
-            payload: { hash: commit, is_scribe: false }
+            payload: { hash: commit }
         });
     } catch(e) {
         bob_pull_failed = true
@@ -224,7 +224,7 @@ export async function mergeFetch(t) {
     let merge_alice = await aliceHapps.cells[0].callZome({
         zome_name: "perspective_diff_sync", 
         fn_name: "pull",
-        payload: { hash: commit_bob, is_scribe: true }
+        payload: { hash: commit_bob }
     });
     //@ts-ignore
     t.isEqual(merge_alice.diff.additions.length, 1);
@@ -242,7 +242,7 @@ export async function mergeFetch(t) {
     let pull_bob3 = await bobHapps.cells[0].callZome({
         zome_name: "perspective_diff_sync", 
         fn_name: "pull",
-        payload: { hash: alice_merge_commit, is_scribe: false }
+        payload: { hash: alice_merge_commit }
     });
     console.warn("bob pull3", pull_bob3);
     //@ts-ignore
@@ -61,7 +61,7 @@ export async function render(t) {
 
     t.assert(firstRenderFailed)
 
-    await call(bobHapps, "pull", { hash: commit2, is_scribe: false })
+    await call(bobHapps, "pull", { hash: commit2 })
 
     console.log("Bob has pulled")
 
@@ -95,7 +95,7 @@ export async function render(t) {
     await sleep(1000);
 
     console.log("RENDER 5")
-    await call(aliceHapps, "pull", { hash: commit5, is_scribe: true }); 
+    await call(aliceHapps, "pull", { hash: commit5 }); 
     let alice_render = await call(aliceHapps, "render");
     console.warn("Alice rendered with", alice_render);
     //@ts-ignore
@@ -237,7 +237,7 @@ export async function renderMerges(t) {
     await sleep(2000)
 
     console.log("bob pull");
-    await call(bobHapps, "pull", { hash: commit6, is_scribe: true })
+    await call(bobHapps, "pull", { hash: commit6 })
     
     console.log("bob render");
     let bob_render2 = await bobHapps.cells[0].callZome({
@@ -138,8 +138,7 @@ async function gossip(peers: Map<DID, PeerInfo>, me: DID, hcDna: HolochainLangua
             }
         }
         let pullResult = await hcDna.call("DNA_NICK", "ZOME_NAME", "pull", { 
-            hash,
-            is_scribe 
+            hash
         });
         if (pullResult) {
             let myRevision = pullResult.current_revision;
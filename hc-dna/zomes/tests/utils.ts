@@ -50,7 +50,11 @@ export async function createConductors(num: number): Promise<{agent_happ: AgentA
                             name: "main",
                             dna: {
                                 //@ts-ignore
-                                path: resolve(dnas[0].source.path)
+                                path: resolve(dnas[0].source.path),
+                                //The links of these tests are authored by made up DIDs
+                                modifiers: {
+                                    properties: { accept_unresolvable_dids: true }
+                                }
                             }
                         }]
                     },
@@ -66,16 +66,6 @@ export class LinkAdapter implements LinkSyncAdapter {
       this.peers.delete(peer);
     }
 
-    // flatten the map into an array of peers
-    let peers = Array.from(this.peers.keys());
-    peers.push(this.me);
-    
-    // Lexically sort the peers
-    peers.sort();
-
-    // If we are the first peer, we are the scribe
-    let is_scribe = peers[0] == this.me;
-    
     // Get a deduped set of all peer's current revisions
     let revisions = new Set<Buffer>();
     for(const peerInfo of this.peers.values()) {
@@ -85,9 +75,9 @@ export class LinkAdapter implements LinkSyncAdapter {
     revisions.forEach( async (hash) => {
       if(!hash) return
       if (this.myCurrentRevision && hash.equals(this.myCurrentRevision)) return
+      // The zome elects the scribe itself, based on the peers it received broadcasts from
       let pullResult = await this.hcDna.call(DNA_NICK, ZOME_NAME, "pull", { 
-        hash,
-        is_scribe 
+        hash
       });
       if (pullResult) {
         if (pullResult.current_revision && Buffer.isBuffer(pullResult.current_revision)) {
@@ -99,6 +89,7 @@ export class LinkAdapter implements LinkSyncAdapter {
 
     //Only show the gossip log every 10th iteration
     if (this.gossipLogCount == 10) {
+      let is_scribe = await this.hcDna.call(DNA_NICK, ZOME_NAME, "am_i_scribe", null);
       console.log(`
       ======
       GOSSIP